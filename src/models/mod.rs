@@ -10,19 +10,14 @@ use std::fmt::Write;
 use num_derive::FromPrimitive;
 use num_traits::FromPrimitive;
 
-#[derive(Debug, FromPrimitive, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[derive(Debug, Default, FromPrimitive, Clone, Copy, PartialEq, Eq, serde::Serialize)]
 pub enum GameMode {
+    #[default]
     Survival = 0,
     TimeAttack,
     Race,
 }
 
-impl std::default::Default for GameMode {
-    fn default() -> Self {
-        GameMode::Survival
-    }
-}
-
 impl std::convert::From<u8> for GameMode {
     fn from(v: u8) -> Self {
         match v {
@@ -168,7 +163,7 @@ impl StatsBlockWithFrames {
         let mut neg_diff_lvl4 = 0;
         let mut last_frame_homing_lvl3 = 0;
         let mut last_frame_homing_lvl4 = 0;
-        let cutoff = if let Some(time) = time { time } else { f32::MAX };
+        let cutoff = time.unwrap_or(f32::MAX);
         for frame in &self.get_frames_until_time(cutoff) {
             if frame.level_gems == 70 {
                 if frame.homing < last_frame_homing_lvl3 {
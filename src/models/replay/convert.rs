@@ -0,0 +1,134 @@
+//
+// conversions between replay formats
+//
+
+use super::*;
+
+// Version written in the header of every `.ddreplay` the game saves
+const DDRPL_FILE_VERSION: u32 = 1;
+
+/// Either kind of replay file, as returned by `open_any_replay`
+#[derive(Debug, Clone)]
+pub enum AnyReplay {
+    DdRpl(Box<DdRpl>), // Boxed because of the arena in the decoded spawnset
+    DfRpl2(DfRpl2),
+}
+
+/// Reads a `.ddreplay` or `DF_RPL2` file, picking the parser from the magic bytes
+pub fn open_any_replay<R: Read>(source: &mut R) -> Result<AnyReplay, ReplayError> {
+    let magic = OffsetReader::new(&mut *source).read_bytes(6)?;
+    let mut source = (&magic[..]).chain(source);
+    match &magic[..] {
+        b"ddrpl." => Ok(AnyReplay::DdRpl(Box::new(DdRpl::from_reader(&mut source)?))),
+        b"DF_RPL" => Ok(AnyReplay::DfRpl2(DfRpl2::from_reader(&mut source)?)),
+        _ => Err(ReplayError::InvalidMagic { found: magic }),
+    }
+}
+
+impl AnyReplay {
+    pub fn player_name(&self) -> &str {
+        match self {
+            AnyReplay::DdRpl(replay) => &replay.header.player_name,
+            AnyReplay::DfRpl2(replay) => &replay.header.player_name,
+        }
+    }
+
+    /// Converts `DF_RPL2` replays, see `DfRpl2::to_ddrpl`
+    pub fn into_ddrpl(self, spawnset_bin: Vec<u8>) -> Result<DdRpl> {
+        match self {
+            AnyReplay::DdRpl(replay) => Ok(*replay),
+            AnyReplay::DfRpl2(replay) => replay.to_ddrpl(spawnset_bin),
+        }
+    }
+}
+
+impl DfRpl2 {
+    /// Builds a `.ddreplay` around the same events.
+    ///
+    /// `DF_RPL2` files don't store the spawnset, the stats or who recorded
+    /// them, so the spawnset has to be given, the stats are counted from the
+    /// events with `ReplayData::header_stats`, the player id is 0 and the
+    /// timestamp is the current time. `funny_bytes` has no place in the
+    /// `.ddreplay` header and is dropped.
+    pub fn to_ddrpl(&self, spawnset_bin: Vec<u8>) -> Result<DdRpl> {
+        let stats = self.data.header_stats();
+        let mut replay = DdRpl {
+            header: DdRplHeader {
+                file_version: DDRPL_FILE_VERSION,
+                recorded_at: SystemTime::now(),
+                time: stats.time,
+                starting_time: 0.,
+                daggers_fired: stats.daggers_fired,
+                death_type: stats.death_type.unwrap_or(0),
+                gems: stats.gems,
+                kills: stats.kills,
+                daggers_hit: stats.daggers_hit,
+                player_name: self.header.player_name.clone(),
+                player_id: 0,
+                unknown: [0; 10],
+                spawnset_hash: spawnset_md5(&spawnset_bin),
+                spawnset_bin,
+                compressed_data_len: 0,
+                spawnset: None,
+            },
+            compressed_data: None,
+            data: Some(self.data.clone()),
+            extra: None,
+        };
+        replay.compress_data()?;
+        Ok(replay)
+    }
+}
+
+impl DdRpl {
+    /// `DF_RPL2` version of the replay, which keeps only the player's name
+    /// and the events
+    pub fn to_dfrpl2(&mut self) -> Result<DfRpl2> {
+        if self.data.is_none() {
+            self.calc_data()?;
+        }
+
+        Ok(DfRpl2::new(
+            DfRpl2Header { player_name: self.header.player_name.clone(), funny_bytes: vec![] },
+            self.data.clone().unwrap(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::replay::tests::test_replay;
+
+    #[test]
+    fn converts_both_ways() {
+        let mut replay = test_replay();
+        let dfrpl2 = replay.to_dfrpl2().unwrap();
+        assert_eq!(dfrpl2.header.player_name, "xvlv");
+
+        let mut written = vec![];
+        dfrpl2.write_to(&mut written).unwrap();
+        let AnyReplay::DfRpl2(parsed) = open_any_replay(&mut &written[..]).unwrap() else { panic!() };
+        assert_eq!(parsed.data().frames, replay.data.as_ref().unwrap().frames);
+
+        let converted = AnyReplay::DfRpl2(parsed).into_ddrpl(replay.header.spawnset_bin.clone()).unwrap();
+        let header = &converted.header;
+        assert!(header.verify_spawnset_hash());
+        assert_eq!((header.daggers_fired, header.gems, header.daggers_hit, header.death_type), (1, 1, 2, 3));
+        assert_eq!(header.time, 2. / 60.);
+
+        let mut written = vec![];
+        converted.write_to(&mut written).unwrap();
+        let AnyReplay::DdRpl(mut parsed) = open_any_replay(&mut &written[..]).unwrap() else { panic!() };
+        parsed.calc_data().unwrap();
+        assert_eq!(parsed.data, replay.data);
+        assert_eq!(parsed.header.player_name, "xvlv");
+    }
+
+    #[test]
+    fn sniffing_rejects_other_files() {
+        assert!(matches!(open_any_replay(&mut &b"MZ\x90\x00\x03\x00\x00"[..]), Err(ReplayError::InvalidMagic { .. })));
+        assert!(matches!(open_any_replay(&mut &b"ddr"[..]), Err(ReplayError::UnexpectedEof { offset: 0, .. })));
+        assert!(matches!(open_any_replay(&mut &b"DF_RPL3"[..]), Err(ReplayError::InvalidMagic { .. })));
+    }
+}
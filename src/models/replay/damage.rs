@@ -0,0 +1,221 @@
+//
+// hit and kill attribution
+//
+
+use std::collections::{BTreeMap, HashMap};
+
+use super::*;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HitCounts {
+    pub weak_spot: u32,
+    pub armor: u32,
+    pub kills: u32,
+}
+
+/// Hits taken by every enemy of one type
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EnemyDamage {
+    pub hits: HitCounts,
+    pub homing_hits: u32,
+    pub spawned: u32,
+    hits_on_killed: u32,
+}
+
+/// Who hit what in a replay.
+///
+/// Kills use the same estimate as `EntityLifecycle::death_frame` and are
+/// credited to the dagger that landed the last weak spot hit. Segments are
+/// only kept for the centipede family, the only enemies made of segments.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DamageBreakdown {
+    pub per_enemy: HashMap<EnemyType, EnemyDamage>,
+    pub per_dagger_level: [HitCounts; 8],
+    pub per_segment: HashMap<EnemyType, BTreeMap<i32, HitCounts>>,
+    pub unattributed_hits: u32, // Hits from daggers that were never spawned
+}
+
+impl HitCounts {
+    pub fn total(&self) -> u32 {
+        self.weak_spot + self.armor
+    }
+
+    /// Share of the hits that landed on armor
+    pub fn armor_ratio(&self) -> Option<f32> {
+        (self.total() > 0).then(|| self.armor as f32 / self.total() as f32)
+    }
+}
+
+impl EnemyDamage {
+    /// Average hits it took to kill one enemy, armor hits included
+    pub fn daggers_per_kill(&self) -> Option<f32> {
+        (self.hits.kills > 0).then(|| self.hits_on_killed as f32 / self.hits.kills as f32)
+    }
+}
+
+impl DamageBreakdown {
+    pub fn homing(&self) -> HitCounts {
+        self.per_dagger_level[DaggerLevel::Level6 as usize]
+    }
+
+    /// Every dagger level except homing
+    pub fn normal(&self) -> HitCounts {
+        self.per_dagger_level.iter()
+            .enumerate()
+            .filter(|(level, _)| *level != DaggerLevel::Level6 as usize)
+            .fold(HitCounts::default(), |sum, (_, hits)| HitCounts {
+                weak_spot: sum.weak_spot + hits.weak_spot,
+                armor: sum.armor + hits.armor,
+                kills: sum.kills + hits.kills,
+            })
+    }
+}
+
+fn is_segmented(enemy_type: EnemyType) -> bool {
+    matches!(enemy_type, EnemyType::Centipede | EnemyType::Gigapede | EnemyType::Ghostpede)
+}
+
+impl ReplayData {
+    pub fn damage_breakdown(&self) -> DamageBreakdown {
+        let mut breakdown = DamageBreakdown::default();
+        let mut tracker = LifecycleTracker::default();
+        let mut dagger_levels = vec![];
+
+        for (frame, replay_frame) in self.frames.iter().enumerate() {
+            for event in &replay_frame.events {
+                let (hit, weak_spot) = match event {
+                    ReplayEvent::Spawn(entity) => {
+                        dagger_levels.push(match entity {
+                            EntityData::Dagger(dagger) => Some(dagger.dagger_level.clone() as usize),
+                            _ => None,
+                        });
+                        if let Some(enemy_type) = EnemyType::from_entity(entity) {
+                            breakdown.per_enemy.entry(enemy_type).or_default().spawned += 1;
+                        }
+                        tracker.apply(frame, event);
+                        continue;
+                    },
+                    ReplayEvent::EnemyHitWeakSpot(hit) => (hit, true),
+                    ReplayEvent::EnemyHitArmor(hit) => (hit, false),
+                    _ => {
+                        tracker.apply(frame, event);
+                        continue;
+                    },
+                };
+
+                let was_alive = tracker.get(hit.enemy_id).is_some_and(|enemy| enemy.death_frame.is_none());
+                tracker.apply(frame, event);
+                let enemy = tracker.get(hit.enemy_id);
+                let killed = was_alive && enemy.is_some_and(|enemy| enemy.death_frame.is_some());
+                let enemy_type = enemy.and_then(|enemy| enemy.enemy_type);
+
+                let count = |hits: &mut HitCounts| {
+                    if weak_spot {
+                        hits.weak_spot += 1;
+                    } else {
+                        hits.armor += 1;
+                    }
+                    if killed {
+                        hits.kills += 1;
+                    }
+                };
+
+                let dagger_level = hit.dagger_id.checked_sub(1)
+                    .and_then(|i| usize::try_from(i).ok())
+                    .and_then(|i| dagger_levels.get(i).copied().flatten());
+                match dagger_level {
+                    Some(level) => count(&mut breakdown.per_dagger_level[level]),
+                    None => breakdown.unattributed_hits += 1,
+                }
+
+                let Some(enemy_type) = enemy_type else { continue };
+                let damage = breakdown.per_enemy.entry(enemy_type).or_default();
+                count(&mut damage.hits);
+                if dagger_level == Some(DaggerLevel::Level6 as usize) {
+                    damage.homing_hits += 1;
+                }
+                if is_segmented(enemy_type) {
+                    count(breakdown.per_segment.entry(enemy_type).or_default().entry(hit.segment).or_default());
+                }
+            }
+        }
+
+        for entity in &tracker.entities {
+            if let (Some(enemy_type), Some(_)) = (entity.enemy_type, entity.death_frame) {
+                breakdown.per_enemy.entry(enemy_type).or_default().hits_on_killed += entity.weak_spot_hits + entity.armor_hits;
+            }
+        }
+
+        breakdown
+    }
+}
+
+impl DdRpl {
+    pub fn damage_breakdown(&mut self) -> Result<DamageBreakdown> {
+        if self.data.is_none() {
+            self.calc_data()?;
+        }
+
+        Ok(self.data.as_ref().unwrap().damage_breakdown())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::replay::tests::test_data;
+
+    #[test]
+    fn attributes_hits_and_kills() {
+        let mut data = test_data();
+        let ReplayEvent::Spawn(EntityData::Dagger(mut homing)) = data.frames[0].events[1].clone() else { panic!() };
+        homing.dagger_level = DaggerLevel::Level6;
+        let pede = PedeData { a: 0, position: [0.; 3], b: [0.; 3], funny1: [0.; 3], funny2: [0.; 3], funny3: [0.; 3] };
+        let hit = |enemy_id, dagger_id, segment| EnemyHitData { enemy_id, dagger_id, segment };
+        let mut events = vec![
+            ReplayEvent::Spawn(EntityData::Dagger(homing)), // 4
+            ReplayEvent::Spawn(EntityData::Centipede(pede)), // 5
+            ReplayEvent::EnemyHitArmor(hit(5, 4, 2)),
+            ReplayEvent::EnemyHitWeakSpot(hit(5, 2, 7)),
+            ReplayEvent::EnemyHitWeakSpot(hit(5, 99, 7)),
+        ];
+        // Skull II has 5 hp, the homing dagger takes the kill
+        events.extend(vec![ReplayEvent::EnemyHitWeakSpot(hit(3, 2, 0)); 4]);
+        events.push(ReplayEvent::EnemyHitArmor(hit(3, 2, 0)));
+        events.push(ReplayEvent::EnemyHitWeakSpot(hit(3, 4, 0)));
+        data.frames[2].events.splice(0..0, events);
+
+        let breakdown = data.damage_breakdown();
+        let skull = &breakdown.per_enemy[&EnemyType::Skull2];
+        assert_eq!(skull.hits, HitCounts { weak_spot: 5, armor: 1, kills: 1 });
+        assert_eq!((skull.homing_hits, skull.spawned), (1, 1));
+        assert_eq!(skull.daggers_per_kill(), Some(6.));
+        assert_eq!(skull.hits.armor_ratio(), Some(1. / 6.));
+
+        let squid = &breakdown.per_enemy[&EnemyType::Squid1];
+        assert_eq!(squid.hits, HitCounts { weak_spot: 1, armor: 1, kills: 0 });
+        assert_eq!(squid.daggers_per_kill(), None);
+
+        assert_eq!(breakdown.homing(), HitCounts { weak_spot: 1, armor: 1, kills: 1 });
+        assert_eq!(breakdown.normal(), HitCounts { weak_spot: 6, armor: 2, kills: 0 });
+        assert_eq!(breakdown.per_dagger_level[DaggerLevel::Level3 as usize], breakdown.normal());
+        assert_eq!(breakdown.unattributed_hits, 1);
+
+        let segments = &breakdown.per_segment[&EnemyType::Centipede];
+        assert_eq!(segments[&2], HitCounts { weak_spot: 0, armor: 1, kills: 0 });
+        assert_eq!(segments[&7], HitCounts { weak_spot: 2, armor: 0, kills: 0 });
+        assert!(!breakdown.per_segment.contains_key(&EnemyType::Skull2));
+    }
+
+    #[test]
+    fn out_of_range_dagger_ids() {
+        let mut data = test_data();
+        for dagger_id in [i32::MIN, 0, -1] {
+            data.frames[2].events.insert(0, ReplayEvent::EnemyHitWeakSpot(EnemyHitData { enemy_id: 1, dagger_id, segment: 0 }));
+        }
+
+        let breakdown = data.damage_breakdown();
+        assert_eq!(breakdown.unattributed_hits, 3);
+        assert_eq!(breakdown.per_enemy[&EnemyType::Squid1].hits.weak_spot, 4);
+    }
+}
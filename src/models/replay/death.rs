@@ -0,0 +1,257 @@
+//
+// death cause reconstruction
+//
+
+use std::fmt;
+use num_derive::FromPrimitive;
+use num_traits::FromPrimitive;
+
+use super::*;
+
+/// Death types as the game numbers them in `PlayerDeathData::death_type`
+#[derive(Debug, Clone, Copy, FromPrimitive, PartialEq, Eq)]
+pub enum DeathType {
+    Fallen = 0,
+    Swarmed,
+    Impaled,
+    Gored,
+    Infested,
+    Opened,
+    Purged,
+    Desecrated,
+    Sacrificed,
+    Eviscerated,
+    Annihilated,
+    Intoxicated,
+    Envenomated,
+    Incarnated,
+    Discarnated,
+    Entangled,
+    Haunted,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NearbyEnemy {
+    pub id: EntityId,
+    pub enemy_type: EnemyType,
+    pub position: Vec3,
+    pub distance: f32,
+}
+
+/// What the world looked like when the player died.
+///
+/// Replays don't store the player's position, the closest thing is where
+/// the last dagger was fired from, so distances are measured from there and
+/// `player_position_frame` says how old that position is.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeathReport {
+    pub frame: usize,
+    pub time: f32,
+    pub death_type: i32,
+    pub player_position: Option<Vec3>,
+    pub player_position_frame: Option<usize>,
+    pub nearest_enemies: Vec<NearbyEnemy>,
+    pub alive: Vec<(EnemyType, u32)>, // In `EnemyType` order
+}
+
+impl DeathType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            DeathType::Fallen => "Fallen",
+            DeathType::Swarmed => "Swarmed",
+            DeathType::Impaled => "Impaled",
+            DeathType::Gored => "Gored",
+            DeathType::Infested => "Infested",
+            DeathType::Opened => "Opened",
+            DeathType::Purged => "Purged",
+            DeathType::Desecrated => "Desecrated",
+            DeathType::Sacrificed => "Sacrificed",
+            DeathType::Eviscerated => "Eviscerated",
+            DeathType::Annihilated => "Annihilated",
+            DeathType::Intoxicated => "Intoxicated",
+            DeathType::Envenomated => "Envenomated",
+            DeathType::Incarnated => "Incarnated",
+            DeathType::Discarnated => "Discarnated",
+            DeathType::Entangled => "Entangled",
+            DeathType::Haunted => "Haunted",
+        }
+    }
+
+    /// The enemy that causes this death, `None` for falling off the arena
+    pub fn killer(&self) -> Option<EnemyType> {
+        Some(match self {
+            DeathType::Fallen => return None,
+            DeathType::Swarmed => EnemyType::Skull1,
+            DeathType::Impaled => EnemyType::Skull2,
+            DeathType::Gored => EnemyType::Skull3,
+            DeathType::Infested => EnemyType::Spiderling,
+            DeathType::Opened => EnemyType::Skull4,
+            DeathType::Purged => EnemyType::Squid1,
+            DeathType::Desecrated => EnemyType::Squid2,
+            DeathType::Sacrificed => EnemyType::Squid3,
+            DeathType::Eviscerated => EnemyType::Centipede,
+            DeathType::Annihilated => EnemyType::Gigapede,
+            DeathType::Intoxicated => EnemyType::Spider1,
+            DeathType::Envenomated => EnemyType::Spider2,
+            DeathType::Incarnated => EnemyType::Leviathan,
+            DeathType::Discarnated => EnemyType::Orb,
+            DeathType::Entangled => EnemyType::Thorn,
+            DeathType::Haunted => EnemyType::Ghostpede,
+        })
+    }
+}
+
+impl EnemyType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            EnemyType::Skull1 => "Skull I",
+            EnemyType::Skull2 => "Skull II",
+            EnemyType::Skull3 => "Skull III",
+            EnemyType::Spiderling => "Spiderling",
+            EnemyType::Skull4 => "Skull IV",
+            EnemyType::Squid1 => "Squid I",
+            EnemyType::Squid2 => "Squid II",
+            EnemyType::Squid3 => "Squid III",
+            EnemyType::Centipede => "Centipede",
+            EnemyType::Gigapede => "Gigapede",
+            EnemyType::Spider1 => "Spider I",
+            EnemyType::Spider2 => "Spider II",
+            EnemyType::Leviathan => "Leviathan",
+            EnemyType::Orb => "The Orb",
+            EnemyType::Thorn => "Thorn",
+            EnemyType::Ghostpede => "Ghostpede",
+            EnemyType::SpiderEgg => "Spider Egg",
+        }
+    }
+}
+
+impl DeathReport {
+    pub fn death_type(&self) -> Option<DeathType> {
+        DeathType::from_i32(self.death_type)
+    }
+
+    /// Closest living enemy of the type that causes this death
+    pub fn suspected_killer(&self) -> Option<&NearbyEnemy> {
+        let killer = self.death_type()?.killer()?;
+        self.nearest_enemies.iter().find(|enemy| enemy.enemy_type == killer)
+    }
+}
+
+impl fmt::Display for DeathReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.death_type().map(|death_type| death_type.killer()) {
+            Some(Some(killer)) => write!(f, "killed by {} at {:.1}s", killer.name(), self.time),
+            Some(None) => write!(f, "fell at {:.1}s", self.time),
+            None => write!(f, "died (death type {}) at {:.1}s", self.death_type, self.time),
+        }
+    }
+}
+
+impl ReplayData {
+    /// Reconstructs the moment of death, `None` if the player never died.
+    /// `nearest` limits how many enemies `nearest_enemies` keeps.
+    pub fn death_report(&self, nearest: usize) -> Option<DeathReport> {
+        let mut player_position = None;
+        let mut death = None;
+        'frames: for (frame, replay_frame) in self.frames.iter().enumerate() {
+            for event in &replay_frame.events {
+                match event {
+                    ReplayEvent::Spawn(EntityData::Dagger(dagger)) => {
+                        player_position = Some((Vec3::from_fixed(&dagger.position), frame));
+                    },
+                    ReplayEvent::PlayerDeath(player_death) => {
+                        death = Some((frame, player_death.death_type));
+                        break 'frames;
+                    },
+                    _ => {},
+                }
+            }
+        }
+        let (frame, death_type) = death?;
+
+        let mut state = self.state();
+        state.seek(frame);
+
+        let mut alive = vec![];
+        for enemy in state.enemies() {
+            let enemy_type = enemy.enemy_type.unwrap();
+            match alive.iter_mut().find(|(alive_type, _)| *alive_type == enemy_type) {
+                Some((_, count)) => *count += 1,
+                None => alive.push((enemy_type, 1)),
+            }
+        }
+        alive.sort_by_key(|(enemy_type, _)| enemy_type.stats_index());
+
+        let mut nearest_enemies = vec![];
+        if let Some((player, _)) = player_position {
+            nearest_enemies = state.enemies()
+                .filter_map(|enemy| {
+                    let position = enemy.world_position()?;
+                    Some(NearbyEnemy {
+                        id: enemy.id,
+                        enemy_type: enemy.enemy_type.unwrap(),
+                        position,
+                        distance: position.distance(player),
+                    })
+                })
+                .collect();
+            nearest_enemies.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+            nearest_enemies.truncate(nearest);
+        }
+
+        Some(DeathReport {
+            frame,
+            time: frame as f32 / FRAMES_PER_SECOND as f32,
+            death_type,
+            player_position: player_position.map(|(position, _)| position),
+            player_position_frame: player_position.map(|(_, frame)| frame),
+            nearest_enemies,
+            alive,
+        })
+    }
+}
+
+impl DdRpl {
+    /// See `ReplayData::death_report`, the time includes the replay's starting time
+    pub fn death_report(&mut self, nearest: usize) -> Result<Option<DeathReport>> {
+        if self.data.is_none() {
+            self.calc_data()?;
+        }
+
+        let mut report = self.data.as_ref().unwrap().death_report(nearest);
+        if let Some(report) = &mut report {
+            report.time += self.header.starting_time;
+        }
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::replay::tests::test_data;
+
+    #[test]
+    fn reconstructs_the_death() {
+        let mut data = test_data();
+        // Move the squid next to where the dagger was fired from
+        data.frames[1].events[0] = ReplayEvent::UpdateEntityPosition(1, [2, 2, 3]);
+
+        let report = data.death_report(5).unwrap();
+        assert_eq!((report.frame, report.death_type), (2, 3));
+        assert_eq!(report.death_type(), Some(DeathType::Gored));
+        assert_eq!(report.player_position, Some(Vec3::from_fixed(&[1, 2, 3])));
+        assert_eq!(report.player_position_frame, Some(0));
+        assert_eq!(report.alive, vec![(EnemyType::Skull2, 1), (EnemyType::Squid1, 1)]);
+
+        let ids = report.nearest_enemies.iter().map(|enemy| enemy.id).collect::<Vec<_>>();
+        assert_eq!(ids, vec![1, 3]);
+        assert_eq!(report.nearest_enemies[0].distance, 1. / 16.);
+        assert_eq!(report.suspected_killer(), None);
+        assert_eq!(report.to_string(), "killed by Skull III at 0.0s");
+
+        assert_eq!(data.death_report(1).unwrap().nearest_enemies.len(), 1);
+        data.frames[2].events.remove(0);
+        assert_eq!(data.death_report(5), None);
+    }
+}
@@ -0,0 +1,419 @@
+//
+// replay event stream decoder
+//
+
+use std::io::{BufReader, Read};
+use num_traits::FromPrimitive;
+
+use super::*;
+
+/// Reader that keeps track of how far into the source it is, so errors can
+/// point at the exact byte that failed to parse.
+pub(crate) struct OffsetReader<R> {
+    inner: R,
+    pub offset: u64,
+    pub frame: Option<usize>,
+    pub event_type: Option<u8>,
+}
+
+impl<R: Read> OffsetReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            offset: 0,
+            frame: None,
+            event_type: None,
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    pub fn read_bytes(&mut self, len: u64) -> Result<Vec<u8>, ReplayError> {
+        // Read through `take` so a corrupt length can't make us allocate
+        // more than what's actually in the source
+        let mut buf = vec![];
+        let start = self.offset;
+        let read = (&mut self.inner).take(len).read_to_end(&mut buf).map_err(|e| self.io_error(e, start))?;
+        self.offset += read as u64;
+        if (read as u64) < len {
+            return Err(self.eof(start));
+        }
+        Ok(buf)
+    }
+
+    pub fn read_to_end(&mut self) -> Result<Vec<u8>, ReplayError> {
+        let mut buf = vec![];
+        let start = self.offset;
+        let read = self.inner.read_to_end(&mut buf).map_err(|e| self.io_error(e, start))?;
+        self.offset += read as u64;
+        Ok(buf)
+    }
+
+    pub fn read_array<const N: usize>(&mut self) -> Result<[u8; N], ReplayError> {
+        let mut buf = [0u8; N];
+        let start = self.offset;
+        self.inner.read_exact(&mut buf).map_err(|e| self.io_error(e, start))?;
+        self.offset += N as u64;
+        Ok(buf)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, ReplayError> {
+        Ok(self.read_array::<1>()?[0])
+    }
+
+    pub fn read_i16(&mut self) -> Result<i16, ReplayError> {
+        Ok(i16::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, ReplayError> {
+        Ok(u16::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_i32(&mut self) -> Result<i32, ReplayError> {
+        Ok(i32::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, ReplayError> {
+        Ok(u32::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, ReplayError> {
+        Ok(u64::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read_f32(&mut self) -> Result<f32, ReplayError> {
+        Ok(f32::from_le_bytes(self.read_array()?))
+    }
+
+    pub fn read3_i16(&mut self) -> Result<[i16; 3], ReplayError> {
+        Ok([self.read_i16()?, self.read_i16()?, self.read_i16()?])
+    }
+
+    pub fn read3_f32(&mut self) -> Result<[f32; 3], ReplayError> {
+        Ok([self.read_f32()?, self.read_f32()?, self.read_f32()?])
+    }
+
+    pub fn eof(&self, offset: u64) -> ReplayError {
+        ReplayError::UnexpectedEof {
+            offset,
+            frame: self.frame,
+            event_type: self.event_type,
+        }
+    }
+
+    fn io_error(&self, e: std::io::Error, offset: u64) -> ReplayError {
+        if e.kind() == std::io::ErrorKind::UnexpectedEof {
+            self.eof(offset)
+        } else {
+            ReplayError::Io(e)
+        }
+    }
+}
+
+/// Lazily decodes a compressed replay event stream, yielding every event
+/// together with the index of the frame it belongs to.
+///
+/// Only the event being decoded is kept in memory, so this is the way to go
+/// when scanning many or very long replays. The decompressor reads the source
+/// in small chunks, so wrap files in a `BufReader`.
+pub struct ReplayEventReader<R: Read> {
+    decoder: EventDecoder<BufReader<libflate::zlib::Decoder<R>>>,
+    failed: bool,
+}
+
+impl<R: Read> ReplayEventReader<R> {
+    pub fn new(source: R) -> Result<Self, ReplayError> {
+        let inflated = libflate::zlib::Decoder::new(source).map_err(ReplayError::Decompression)?;
+        Ok(Self {
+            decoder: EventDecoder::new(BufReader::new(inflated)),
+            failed: false,
+        })
+    }
+
+    /// Reads whatever comes after the end of the replay, see `ReplayData::trailing_bytes`
+    pub fn read_trailing(&mut self) -> Result<Vec<u8>, ReplayError> {
+        self.decoder.read_trailing().map_err(decompression_error)
+    }
+}
+
+impl<R: Read> Iterator for ReplayEventReader<R> {
+    type Item = Result<(usize, ReplayEvent), ReplayError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+
+        let frame = self.decoder.frame;
+        match self.decoder.next_event() {
+            Ok(event) => event.map(|event| Ok((frame, event))),
+            Err(e) => {
+                self.failed = true;
+                Some(Err(decompression_error(e)))
+            },
+        }
+    }
+}
+
+// Reading the inflated stream can only fail with something other than EOF
+// if the decompressor itself did
+fn decompression_error(e: ReplayError) -> ReplayError {
+    match e {
+        ReplayError::Io(e) => ReplayError::Decompression(e),
+        e => e,
+    }
+}
+
+/// Decodes the uncompressed event stream one event at a time.
+pub(crate) struct EventDecoder<R> {
+    reader: OffsetReader<R>,
+    frame: usize,
+    first: bool,
+    finished: bool,
+}
+
+impl<R: Read> EventDecoder<R> {
+    pub fn new(source: R) -> Self {
+        Self {
+            reader: OffsetReader::new(source),
+            frame: 0,
+            first: true,
+            finished: false,
+        }
+    }
+
+    /// Bytes left in the stream once the replay has ended
+    pub fn read_trailing(&mut self) -> Result<Vec<u8>, ReplayError> {
+        self.reader.frame = None;
+        self.reader.event_type = None;
+        self.reader.read_to_end()
+    }
+
+    pub fn next_event(&mut self) -> Result<Option<ReplayEvent>, ReplayError> {
+        if self.finished {
+            return Ok(None);
+        }
+
+        let r = &mut self.reader;
+        let frame = self.frame;
+        r.frame = Some(frame);
+        r.event_type = None;
+        let event_type = r.read_u8()?;
+        r.event_type = Some(event_type);
+
+        let event = match event_type {
+            0x0 => {
+                let offset = r.offset;
+                let value = r.read_u8()?;
+                let entity_type: EntityType = FromPrimitive::from_u8(value)
+                    .ok_or(ReplayError::InvalidEntityType { offset, frame, value })?;
+
+                ReplayEvent::Spawn(match entity_type {
+                    EntityType::Dagger => EntityData::Dagger(DaggerData {
+                        a: r.read_i32()?,
+                        position: r.read3_i16()?,
+                        orientationa: r.read3_i16()?,
+                        orientationb: r.read3_i16()?,
+                        orientationc: r.read3_i16()?,
+                        b: r.read_u8()?,
+                        dagger_level: {
+                            let offset = r.offset;
+                            let value = r.read_u8()?;
+                            FromPrimitive::from_u8(value).ok_or(ReplayError::InvalidDaggerLevel { offset, frame, value })?
+                        },
+                    }),
+                    EntityType::Squid1 => EntityData::Squid1(read_squid(r)?),
+                    EntityType::Squid2 => EntityData::Squid2(read_squid(r)?),
+                    EntityType::Squid3 => EntityData::Squid3(read_squid(r)?),
+                    EntityType::Boid => EntityData::Boid(BoidData {
+                        spanwer: r.read_i32()?,
+                        boid_type: {
+                            let offset = r.offset;
+                            let value = r.read_u8()?;
+                            FromPrimitive::from_u8(value).ok_or(ReplayError::InvalidBoidType { offset, frame, value })?
+                        },
+                        position: r.read3_i16()?,
+                        funny1: r.read3_i16()?,
+                        funny2: r.read3_i16()?,
+                        funny3: r.read3_i16()?,
+                        funny4: r.read3_f32()?,
+                        speed: r.read_f32()?,
+                    }),
+                    EntityType::Centipede => EntityData::Centipede(read_pede(r)?),
+                    EntityType::Gigapede => EntityData::Gigapede(read_pede(r)?),
+                    EntityType::Ghostpede => EntityData::Ghostpede(read_pede(r)?),
+                    EntityType::Spider1 => EntityData::Spider1(read_spider(r)?),
+                    EntityType::Spider2 => EntityData::Spider2(read_spider(r)?),
+                    EntityType::Egg => EntityData::Egg(EggData {
+                        spider_spawner: r.read_i32()?,
+                        funny1: r.read3_f32()?,
+                        funny2: r.read3_f32()?,
+                    }),
+                    EntityType::Thorn => EntityData::Thorn(ThornData {
+                        a: r.read_i32()?,
+                        position: r.read3_f32()?,
+                        rotation: r.read_f32()?,
+                    }),
+                    EntityType::Leviathan => EntityData::Leviathan(r.read_i32()?),
+                })
+            },
+            0x1 => ReplayEvent::UpdateEntityPosition(r.read_i32()?, r.read3_i16()?),
+            0x2 => ReplayEvent::UpdateEntityOrientation(
+                r.read_i32()?,
+                UpdateOrientationData {
+                    a: r.read3_i16()?,
+                    b: r.read3_i16()?,
+                    c: r.read3_i16()?,
+                }
+            ),
+            0x4 => ReplayEvent::UpdateEntityTarget(r.read_i32()?, r.read3_i16()?),
+            0x5 => {
+                let a = r.read_i32()?;
+                let b = r.read_i32()?;
+                let c = r.read_i32()?;
+
+                if a == 0 {
+                    ReplayEvent::PlayerDeath(PlayerDeathData {
+                        death_type: b,
+                        unknown: c,
+                    })
+                } else if b == 0 && c == 0 {
+                    ReplayEvent::DaggerDewspawn(DaggerDespawnData {
+                        dagger_id: a
+                    })
+                } else if a < 0 {
+                    ReplayEvent::EnemyHitArmor(EnemyHitData {
+                        enemy_id: a.wrapping_neg(),
+                        dagger_id: b,
+                        segment: c
+                    })
+                } else {
+                    ReplayEvent::EnemyHitWeakSpot(EnemyHitData {
+                        enemy_id: a,
+                        dagger_id: b,
+                        segment: c
+                    })
+                }
+            },
+            0x6 => ReplayEvent::GemPickup,
+            0x7 => ReplayEvent::Transmute(
+                r.read_i32()?,
+                TransmuteData {
+                    a: r.read3_i16()?,
+                    b: r.read3_i16()?,
+                    c: r.read3_i16()?,
+                    d: r.read3_i16()?,
+                }
+            ),
+            0x9 => {
+                let buttons = ButtonData {
+                    left: KeyState::from(r.read_u8()?),
+                    right: KeyState::from(r.read_u8()?),
+                    forward: KeyState::from(r.read_u8()?),
+                    backwards: KeyState::from(r.read_u8()?),
+                    jump: JumpButtonState::from(r.read_u8()?),
+                    shoot: MouseButtonState::from(r.read_u8()?),
+                    homing: MouseButtonState::from(r.read_u8()?),
+                };
+
+                let mut mouse_data = MouseData {
+                    x: r.read_i16()?,
+                    y: r.read_i16()?,
+                    look_speed: None,
+                };
+
+                if self.first {
+                    mouse_data.look_speed = Some(LOOK_SPEED_SCALE * r.read_f32()?);
+                    self.first = false;
+                }
+
+                let offset = r.offset;
+                let value = r.read_u8()?;
+                if value != 0xA {
+                    return Err(ReplayError::InvalidFrameTerminator { offset, frame, value });
+                }
+
+                self.frame += 1;
+                ReplayEvent::EndFrame(buttons, mouse_data)
+            },
+            0xB => {
+                self.finished = true;
+                ReplayEvent::EndReplay
+            },
+            _ => {
+                self.finished = true;
+                ReplayEvent::Unknown(event_type, r.read_to_end()?)
+            },
+        };
+
+        Ok(Some(event))
+    }
+}
+
+fn read_squid<R: Read>(r: &mut OffsetReader<R>) -> Result<SquidData, ReplayError> {
+    Ok(SquidData {
+        a: r.read_i32()?,
+        position: r.read3_f32()?,
+        b: r.read3_f32()?,
+        rotation: r.read_f32()?,
+    })
+}
+
+fn read_pede<R: Read>(r: &mut OffsetReader<R>) -> Result<PedeData, ReplayError> {
+    Ok(PedeData {
+        a: r.read_i32()?,
+        position: r.read3_f32()?,
+        b: r.read3_f32()?,
+        funny1: r.read3_f32()?,
+        funny2: r.read3_f32()?,
+        funny3: r.read3_f32()?,
+    })
+}
+
+fn read_spider<R: Read>(r: &mut OffsetReader<R>) -> Result<SpiderData, ReplayError> {
+    Ok(SpiderData {
+        a: r.read_i32()?,
+        position: r.read3_f32()?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::replay::tests::test_data;
+
+    #[test]
+    fn streams_the_same_events() {
+        let data = test_data();
+        let mut compressed = vec![];
+        data.write_to(&mut compressed).unwrap();
+
+        let streamed: Vec<(usize, ReplayEvent)> = ReplayEventReader::new(&compressed[..])
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let expected: Vec<(usize, ReplayEvent)> = data.frames
+            .iter()
+            .enumerate()
+            .flat_map(|(i, frame)| frame.events.iter().map(move |event| (i, event.clone())))
+            .collect();
+        assert_eq!(streamed, expected);
+    }
+
+    #[test]
+    fn stops_after_an_error() {
+        let mut events = test_data().encode_events().unwrap();
+        let last = events.len() - 1;
+        events[last] = 0x0; // spawn with no data instead of the end of the replay
+        let mut encoder = libflate::zlib::Encoder::new(Vec::new()).unwrap();
+        std::io::Write::write_all(&mut encoder, &events).unwrap();
+        let compressed = encoder.finish().into_result().unwrap();
+
+        let mut reader = ReplayEventReader::new(&compressed[..]).unwrap();
+        assert!(reader.by_ref().take(3).all(|item| item.is_ok()));
+        assert!(matches!(reader.by_ref().last(), Some(Err(ReplayError::UnexpectedEof { frame: Some(2), event_type: Some(0x0), .. }))));
+        assert!(reader.next().is_none());
+    }
+}
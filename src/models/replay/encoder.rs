@@ -0,0 +1,291 @@
+//
+// replay event stream encoder
+//
+
+use std::io::Write;
+use anyhow::Result;
+use bytestream::{ByteOrder, StreamWriter};
+
+use super::*;
+
+impl ReplayData {
+    /// Encodes the frames back into the uncompressed event stream, the exact
+    /// inverse of what `ReplayData::from_reader` decodes.
+    ///
+    /// The look speed is only stored once in the stream, so only the
+    /// `MouseData::look_speed` of the first `EndFrame` is written and it
+    /// can't be `None`. Hits, deaths and dagger despawns share an event type
+    /// and are told apart by their values, events that would be read back as
+    /// a different one, like a weak spot hit with a 0 dagger id and segment,
+    /// are refused.
+    pub fn encode_events(&self) -> Result<Vec<u8>> {
+        let mut sink = vec![];
+        let mut first = true;
+        for frame in &self.frames {
+            for event in &frame.events {
+                write_event(&mut sink, event, &mut first)?;
+            }
+        }
+        sink.extend_from_slice(&self.trailing_bytes);
+        Ok(sink)
+    }
+
+    /// Encodes and zlib compresses the event stream, producing the payload
+    /// stored in `.ddreplay` and `DF_RPL2` files.
+    pub fn write_to<W: Write>(&self, sink: &mut W) -> Result<()> {
+        let mut encoder = libflate::zlib::Encoder::new(sink)?;
+        encoder.write_all(&self.encode_events()?)?;
+        encoder.finish().into_result()?.flush()?;
+        Ok(())
+    }
+}
+
+fn write_event<W: Write>(sink: &mut W, event: &ReplayEvent, first: &mut bool) -> Result<()> {
+    match event {
+        ReplayEvent::Spawn(entity) => {
+            sink.write_all(&[0x0, entity.entity_type() as u8])?;
+            match entity {
+                EntityData::Dagger(dagger) => {
+                    write_entity_id(sink, dagger.a)?;
+                    write3_i16(sink, &dagger.position)?;
+                    write3_i16(sink, &dagger.orientationa)?;
+                    write3_i16(sink, &dagger.orientationb)?;
+                    write3_i16(sink, &dagger.orientationc)?;
+                    sink.write_all(&[dagger.b, dagger.dagger_level.clone() as u8])?;
+                },
+                EntityData::Squid1(squid) | EntityData::Squid2(squid) | EntityData::Squid3(squid) => {
+                    write_entity_id(sink, squid.a)?;
+                    write3_f32(sink, &squid.position)?;
+                    write3_f32(sink, &squid.b)?;
+                    write_f32(sink, squid.rotation)?;
+                },
+                EntityData::Boid(boid) => {
+                    write_entity_id(sink, boid.spanwer)?;
+                    sink.write_all(&[boid.boid_type.clone() as u8])?;
+                    write3_i16(sink, &boid.position)?;
+                    write3_i16(sink, &boid.funny1)?;
+                    write3_i16(sink, &boid.funny2)?;
+                    write3_i16(sink, &boid.funny3)?;
+                    write3_f32(sink, &boid.funny4)?;
+                    write_f32(sink, boid.speed)?;
+                },
+                EntityData::Centipede(pede) | EntityData::Gigapede(pede) | EntityData::Ghostpede(pede) => {
+                    write_entity_id(sink, pede.a)?;
+                    write3_f32(sink, &pede.position)?;
+                    write3_f32(sink, &pede.b)?;
+                    write3_f32(sink, &pede.funny1)?;
+                    write3_f32(sink, &pede.funny2)?;
+                    write3_f32(sink, &pede.funny3)?;
+                },
+                EntityData::Spider1(spider) | EntityData::Spider2(spider) => {
+                    write_entity_id(sink, spider.a)?;
+                    write3_f32(sink, &spider.position)?;
+                },
+                EntityData::Egg(egg) => {
+                    write_entity_id(sink, egg.spider_spawner)?;
+                    write3_f32(sink, &egg.funny1)?;
+                    write3_f32(sink, &egg.funny2)?;
+                },
+                EntityData::Thorn(thorn) => {
+                    write_entity_id(sink, thorn.a)?;
+                    write3_f32(sink, &thorn.position)?;
+                    write_f32(sink, thorn.rotation)?;
+                },
+                EntityData::Leviathan(leviathan) => write_entity_id(sink, *leviathan)?,
+            }
+        },
+        ReplayEvent::UpdateEntityPosition(id, position) => {
+            sink.write_all(&[0x1])?;
+            write_entity_id(sink, *id)?;
+            write3_i16(sink, position)?;
+        },
+        ReplayEvent::UpdateEntityOrientation(id, orientation) => {
+            sink.write_all(&[0x2])?;
+            write_entity_id(sink, *id)?;
+            write3_i16(sink, &orientation.a)?;
+            write3_i16(sink, &orientation.b)?;
+            write3_i16(sink, &orientation.c)?;
+        },
+        ReplayEvent::UpdateEntityTarget(id, target) => {
+            sink.write_all(&[0x4])?;
+            write_entity_id(sink, *id)?;
+            write3_i16(sink, target)?;
+        },
+        ReplayEvent::PlayerDeath(death) => write_hit(sink, 0, death.death_type, death.unknown)?,
+        ReplayEvent::DaggerDewspawn(despawn) if despawn.dagger_id != 0 => write_hit(sink, despawn.dagger_id, 0, 0)?,
+        ReplayEvent::EnemyHitArmor(hit) if hit.enemy_id.wrapping_neg() < 0 && (hit.dagger_id, hit.segment) != (0, 0) => {
+            write_hit(sink, hit.enemy_id.wrapping_neg(), hit.dagger_id, hit.segment)?;
+        },
+        ReplayEvent::EnemyHitWeakSpot(hit) if hit.enemy_id > 0 && (hit.dagger_id, hit.segment) != (0, 0) => {
+            write_hit(sink, hit.enemy_id, hit.dagger_id, hit.segment)?;
+        },
+        ReplayEvent::DaggerDewspawn(_) | ReplayEvent::EnemyHitArmor(_) | ReplayEvent::EnemyHitWeakSpot(_) => {
+            bail!("{:?} would be read back as a different event", event);
+        },
+        ReplayEvent::GemPickup => sink.write_all(&[0x6])?,
+        ReplayEvent::Transmute(id, transmute) => {
+            sink.write_all(&[0x7])?;
+            write_entity_id(sink, *id)?;
+            write3_i16(sink, &transmute.a)?;
+            write3_i16(sink, &transmute.b)?;
+            write3_i16(sink, &transmute.c)?;
+            write3_i16(sink, &transmute.d)?;
+        },
+        ReplayEvent::EndFrame(buttons, mouse) => {
+            sink.write_all(&[
+                0x9,
+                u8::from(&buttons.left),
+                u8::from(&buttons.right),
+                u8::from(&buttons.forward),
+                u8::from(&buttons.backwards),
+                u8::from(&buttons.jump),
+                u8::from(&buttons.shoot),
+                u8::from(&buttons.homing),
+            ])?;
+            mouse.x.write_to(sink, ByteOrder::LittleEndian)?;
+            mouse.y.write_to(sink, ByteOrder::LittleEndian)?;
+            if *first {
+                let Some(look_speed) = mouse.look_speed else { bail!("The first EndFrame has no look speed") };
+                write_f32(sink, look_speed_to_raw(look_speed))?;
+                *first = false;
+            }
+            sink.write_all(&[0xA])?;
+        },
+        ReplayEvent::EndReplay => sink.write_all(&[0xB])?,
+        ReplayEvent::Unknown(event_type, rest) => {
+            sink.write_all(&[*event_type])?;
+            sink.write_all(rest)?;
+        },
+    }
+    Ok(())
+}
+
+fn write_hit<W: Write>(sink: &mut W, a: i32, b: i32, c: i32) -> Result<()> {
+    sink.write_all(&[0x5])?;
+    a.write_to(sink, ByteOrder::LittleEndian)?;
+    b.write_to(sink, ByteOrder::LittleEndian)?;
+    c.write_to(sink, ByteOrder::LittleEndian)?;
+    Ok(())
+}
+
+// The stream stores the raw sensitivity, which gets scaled on read. Dividing
+// the scale back out can land an ulp or two away from the stored value, so
+// search the neighbourhood for the float that scales back exactly.
+fn look_speed_to_raw(look_speed: f32) -> f32 {
+    let raw = look_speed / LOOK_SPEED_SCALE;
+    (-4i64..=4)
+        .filter_map(|offset| u32::try_from(raw.to_bits() as i64 + offset).ok())
+        .map(f32::from_bits)
+        .find(|candidate| LOOK_SPEED_SCALE * candidate == look_speed)
+        .unwrap_or(raw)
+}
+
+fn write_entity_id<W: Write>(sink: &mut W, id: EntityId) -> Result<()> {
+    id.write_to(sink, ByteOrder::LittleEndian)?;
+    Ok(())
+}
+
+fn write3_i16<W: Write>(sink: &mut W, v: &[i16; 3]) -> Result<()> {
+    for x in v {
+        x.write_to(sink, ByteOrder::LittleEndian)?;
+    }
+    Ok(())
+}
+
+fn write_f32<W: Write>(sink: &mut W, v: f32) -> Result<()> {
+    sink.write_all(&v.to_le_bytes())?;
+    Ok(())
+}
+
+fn write3_f32<W: Write>(sink: &mut W, v: &[f32; 3]) -> Result<()> {
+    for x in v {
+        write_f32(sink, *x)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::replay::tests::test_data;
+
+    #[test]
+    fn encode_round_trip() {
+        let data = test_data();
+        let mut compressed = vec![];
+        data.write_to(&mut compressed).unwrap();
+
+        let decoded = ReplayData::from_reader(&mut &compressed[..]).unwrap();
+        assert_eq!(decoded.frames, data.frames);
+        assert_eq!(decoded.entities.len(), 3);
+        assert_eq!(decoded.encode_events().unwrap(), data.encode_events().unwrap());
+    }
+
+    fn empty_frame_events() -> Vec<u8> {
+        let mut events = vec![0x9, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        events.extend_from_slice(&1f32.to_le_bytes());
+        events.push(0xA);
+        events
+    }
+
+    #[test]
+    fn unknown_bytes_round_trip() {
+        let mut events = empty_frame_events();
+        events[1] = 2; // left
+        events[5] = 7; // jump
+        events[6] = 9; // shoot
+        events.extend_from_slice(&[0x5, 0, 0, 0, 0, 2, 0, 0, 0, 0x2A, 0, 0, 0]);
+        events.extend_from_slice(&[0x3, 0xDE, 0xAD, 0xBE, 0xEF]);
+        let mut encoder = libflate::zlib::Encoder::new(Vec::new()).unwrap();
+        encoder.write_all(&events).unwrap();
+        let compressed = encoder.finish().into_result().unwrap();
+
+        let decoded = ReplayData::from_reader(&mut &compressed[..]).unwrap();
+        let ReplayEvent::EndFrame(buttons, _) = &decoded.frames[0].events[0] else { panic!() };
+        assert_eq!(buttons.left, KeyState::Unknown(2));
+        let last = &decoded.frames.last().unwrap().events;
+        assert_eq!(last[0], ReplayEvent::PlayerDeath(PlayerDeathData { death_type: 2, unknown: 0x2A }));
+        assert_eq!(last[1], ReplayEvent::Unknown(0x3, vec![0xDE, 0xAD, 0xBE, 0xEF]));
+        assert_eq!(decoded.encode_events().unwrap(), events);
+
+        let mut events = empty_frame_events();
+        events.extend_from_slice(&[0xB, 1, 2, 3]);
+        let mut encoder = libflate::zlib::Encoder::new(Vec::new()).unwrap();
+        encoder.write_all(&events).unwrap();
+        let compressed = encoder.finish().into_result().unwrap();
+        let decoded = ReplayData::from_reader(&mut &compressed[..]).unwrap();
+        assert_eq!(decoded.trailing_bytes, vec![1, 2, 3]);
+        assert_eq!(decoded.encode_events().unwrap(), events);
+    }
+
+    #[test]
+    fn refuses_ambiguous_events() {
+        let hit = |enemy_id, dagger_id, segment| EnemyHitData { enemy_id, dagger_id, segment };
+        for event in [
+            ReplayEvent::EnemyHitWeakSpot(hit(3, 0, 0)),
+            ReplayEvent::EnemyHitWeakSpot(hit(0, 2, 1)),
+            ReplayEvent::EnemyHitArmor(hit(-3, 2, 1)),
+            ReplayEvent::DaggerDewspawn(DaggerDespawnData { dagger_id: 0 }),
+        ] {
+            let mut data = test_data();
+            data.frames[1].events.insert(0, event);
+            assert!(data.encode_events().is_err());
+        }
+
+        let mut data = test_data();
+        data.frames[1].events.insert(0, ReplayEvent::EnemyHitArmor(hit(i32::MIN, 2, 1)));
+        assert!(data.encode_events().is_ok());
+
+        let ReplayEvent::EndFrame(_, mouse) = data.frames[0].events.last_mut().unwrap() else { panic!() };
+        mouse.look_speed = None;
+        assert!(data.encode_events().is_err());
+    }
+
+    #[test]
+    fn look_speed_survives_scaling() {
+        for i in 1..2000 {
+            let look_speed = LOOK_SPEED_SCALE * (i as f32 * 0.00731);
+            assert_eq!(LOOK_SPEED_SCALE * look_speed_to_raw(look_speed), look_speed);
+        }
+    }
+}
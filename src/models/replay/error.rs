@@ -0,0 +1,76 @@
+//
+// replay parsing errors
+//
+
+use std::fmt;
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(std::io::Error),
+    Decompression(std::io::Error),
+    UnexpectedEof { offset: u64, frame: Option<usize>, event_type: Option<u8> },
+    InvalidMagic { found: Vec<u8> },
+    InvalidUsername { offset: u64 },
+    InvalidTimestamp { offset: u64, value: u64 },
+    DataTooLarge { offset: u64, len: u32 },
+    SpawnsetHashMismatch { offset: u64, expected: String, found: String },
+    TrailingData { offset: u64 },
+    InvalidEntityType { offset: u64, frame: usize, value: u8 },
+    InvalidBoidType { offset: u64, frame: usize, value: u8 },
+    InvalidDaggerLevel { offset: u64, frame: usize, value: u8 },
+    InvalidFrameTerminator { offset: u64, frame: usize, value: u8 },
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::Io(e) => write!(f, "io error: {}", e),
+            ReplayError::Decompression(e) => write!(f, "failed to decompress replay data: {}", e),
+            ReplayError::UnexpectedEof { offset, frame, event_type } => {
+                write!(f, "unexpected end of data at offset {}", offset)?;
+                if let Some(frame) = frame {
+                    write!(f, " in frame {}", frame)?;
+                }
+                if let Some(event_type) = event_type {
+                    write!(f, " while reading event 0x{:X}", event_type)?;
+                }
+                Ok(())
+            },
+            ReplayError::InvalidMagic { found } => write!(f, "invalid replay magic {:?}", found),
+            ReplayError::InvalidUsername { offset } => write!(f, "username at offset {} is not valid utf-8", offset),
+            ReplayError::InvalidTimestamp { offset, value } => write!(f, "invalid timestamp {} at offset {}", value, offset),
+            ReplayError::DataTooLarge { offset, len } => write!(f, "replay data at offset {} is too big ({} bytes)", offset, len),
+            ReplayError::SpawnsetHashMismatch { offset, expected, found } => {
+                write!(f, "spawnset hash at offset {} is {} but the embedded spawnset hashes to {}", offset, expected, found)
+            },
+            ReplayError::TrailingData { offset } => write!(f, "unexpected data after the end of the replay at offset {}", offset),
+            ReplayError::InvalidEntityType { offset, frame, value } => {
+                write!(f, "invalid entity type 0x{:X} at offset {} in frame {}", value, offset, frame)
+            },
+            ReplayError::InvalidBoidType { offset, frame, value } => {
+                write!(f, "invalid boid type {} at offset {} in frame {}", value, offset, frame)
+            },
+            ReplayError::InvalidDaggerLevel { offset, frame, value } => {
+                write!(f, "invalid dagger level {} at offset {} in frame {}", value, offset, frame)
+            },
+            ReplayError::InvalidFrameTerminator { offset, frame, value } => {
+                write!(f, "expected end of frame 0xA but found 0x{:X} at offset {} in frame {}", value, offset, frame)
+            },
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ReplayError::Io(e) | ReplayError::Decompression(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl std::convert::From<std::io::Error> for ReplayError {
+    fn from(e: std::io::Error) -> Self {
+        ReplayError::Io(e)
+    }
+}
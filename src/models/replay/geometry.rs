@@ -0,0 +1,292 @@
+//
+// world space conversions for replay positions and orientations
+//
+
+use std::ops::{Add, Mul, Neg, Sub};
+
+use super::*;
+
+/// Fixed point positions (`PositionInt`) are stored in 1/16ths of a world unit
+pub const POSITION_SCALE: f32 = 16.;
+
+/// Orientation rows are unit vectors stored as i16, with 1.0 mapped to `i16::MAX`
+pub const ORIENTATION_SCALE: f32 = i16::MAX as f32;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Vec3 {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+/// Row major 3x3 matrix, rows in the order they are stored in the replay
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mat3 {
+    pub rows: [Vec3; 3],
+}
+
+/// Unit quaternion, `w` is the scalar part
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quat {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub w: f32,
+}
+
+impl Vec3 {
+    pub const ZERO: Vec3 = Vec3 { x: 0., y: 0., z: 0. };
+
+    pub fn new(x: f32, y: f32, z: f32) -> Self {
+        Self { x, y, z }
+    }
+
+    pub fn from_fixed(v: &PositionInt) -> Self {
+        Self::new(v[0] as f32, v[1] as f32, v[2] as f32) * (1. / POSITION_SCALE)
+    }
+
+    pub fn from_orientation_row(v: &[i16; 3]) -> Self {
+        Self::new(v[0] as f32, v[1] as f32, v[2] as f32) * (1. / ORIENTATION_SCALE)
+    }
+
+    pub fn dot(&self, other: Vec3) -> f32 {
+        self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    pub fn cross(&self, other: Vec3) -> Vec3 {
+        Vec3::new(
+            self.y * other.z - self.z * other.y,
+            self.z * other.x - self.x * other.z,
+            self.x * other.y - self.y * other.x,
+        )
+    }
+
+    pub fn length(&self) -> f32 {
+        self.dot(*self).sqrt()
+    }
+
+    pub fn distance(&self, other: Vec3) -> f32 {
+        (*self - other).length()
+    }
+
+    /// Distance ignoring height, which is what matters on the arena floor
+    pub fn distance_xz(&self, other: Vec3) -> f32 {
+        Vec3::new(self.x - other.x, 0., self.z - other.z).length()
+    }
+
+    pub fn normalized(&self) -> Vec3 {
+        let length = self.length();
+        if length == 0. { *self } else { *self * (1. / length) }
+    }
+}
+
+impl From<PositionFloat> for Vec3 {
+    fn from(v: PositionFloat) -> Self {
+        Self::new(v[0], v[1], v[2])
+    }
+}
+
+impl From<Vec3> for PositionFloat {
+    fn from(v: Vec3) -> Self {
+        [v.x, v.y, v.z]
+    }
+}
+
+impl Add for Vec3 {
+    type Output = Vec3;
+
+    fn add(self, rhs: Vec3) -> Vec3 {
+        Vec3::new(self.x + rhs.x, self.y + rhs.y, self.z + rhs.z)
+    }
+}
+
+impl Sub for Vec3 {
+    type Output = Vec3;
+
+    fn sub(self, rhs: Vec3) -> Vec3 {
+        Vec3::new(self.x - rhs.x, self.y - rhs.y, self.z - rhs.z)
+    }
+}
+
+impl Mul<f32> for Vec3 {
+    type Output = Vec3;
+
+    fn mul(self, rhs: f32) -> Vec3 {
+        Vec3::new(self.x * rhs, self.y * rhs, self.z * rhs)
+    }
+}
+
+impl Neg for Vec3 {
+    type Output = Vec3;
+
+    fn neg(self) -> Vec3 {
+        self * -1.
+    }
+}
+
+impl Mat3 {
+    pub const IDENTITY: Mat3 = Mat3 {
+        rows: [
+            Vec3 { x: 1., y: 0., z: 0. },
+            Vec3 { x: 0., y: 1., z: 0. },
+            Vec3 { x: 0., y: 0., z: 1. },
+        ],
+    };
+
+    pub fn from_fixed(a: &[i16; 3], b: &[i16; 3], c: &[i16; 3]) -> Self {
+        Self {
+            rows: [
+                Vec3::from_orientation_row(a),
+                Vec3::from_orientation_row(b),
+                Vec3::from_orientation_row(c),
+            ],
+        }
+    }
+
+    pub fn transpose(&self) -> Mat3 {
+        let [a, b, c] = self.rows;
+        Mat3 {
+            rows: [
+                Vec3::new(a.x, b.x, c.x),
+                Vec3::new(a.y, b.y, c.y),
+                Vec3::new(a.z, b.z, c.z),
+            ],
+        }
+    }
+
+    pub fn mul_vec(&self, v: Vec3) -> Vec3 {
+        Vec3::new(self.rows[0].dot(v), self.rows[1].dot(v), self.rows[2].dot(v))
+    }
+
+    /// Assumes a rotation matrix, fixed point rounding is tolerated since the
+    /// result gets normalized
+    pub fn to_quat(&self) -> Quat {
+        let [r0, r1, r2] = self.rows;
+        let trace = r0.x + r1.y + r2.z;
+        let q = if trace > 0. {
+            let s = (trace + 1.).sqrt() * 2.;
+            Quat { w: 0.25 * s, x: (r2.y - r1.z) / s, y: (r0.z - r2.x) / s, z: (r1.x - r0.y) / s }
+        } else if r0.x > r1.y && r0.x > r2.z {
+            let s = (1. + r0.x - r1.y - r2.z).sqrt() * 2.;
+            Quat { w: (r2.y - r1.z) / s, x: 0.25 * s, y: (r0.y + r1.x) / s, z: (r0.z + r2.x) / s }
+        } else if r1.y > r2.z {
+            let s = (1. + r1.y - r0.x - r2.z).sqrt() * 2.;
+            Quat { w: (r0.z - r2.x) / s, x: (r0.y + r1.x) / s, y: 0.25 * s, z: (r1.z + r2.y) / s }
+        } else {
+            let s = (1. + r2.z - r0.x - r1.y).sqrt() * 2.;
+            Quat { w: (r1.x - r0.y) / s, x: (r0.z + r2.x) / s, y: (r1.z + r2.y) / s, z: 0.25 * s }
+        };
+        q.normalized()
+    }
+}
+
+impl Quat {
+    pub const IDENTITY: Quat = Quat { x: 0., y: 0., z: 0., w: 1. };
+
+    pub fn from_axis_angle(axis: Vec3, radians: f32) -> Self {
+        let axis = axis.normalized();
+        let (sin, cos) = (radians * 0.5).sin_cos();
+        Quat { x: axis.x * sin, y: axis.y * sin, z: axis.z * sin, w: cos }
+    }
+
+    /// Rotation around the vertical axis, as used by the `rotation` of squids and thorns
+    pub fn from_yaw(radians: f32) -> Self {
+        Self::from_axis_angle(Vec3::new(0., 1., 0.), radians)
+    }
+
+    pub fn normalized(&self) -> Quat {
+        let length = (self.x * self.x + self.y * self.y + self.z * self.z + self.w * self.w).sqrt();
+        if length == 0. {
+            return Quat::IDENTITY;
+        }
+        Quat { x: self.x / length, y: self.y / length, z: self.z / length, w: self.w / length }
+    }
+
+    pub fn to_mat3(&self) -> Mat3 {
+        let Quat { x, y, z, w } = *self;
+        Mat3 {
+            rows: [
+                Vec3::new(1. - 2. * (y * y + z * z), 2. * (x * y - z * w), 2. * (x * z + y * w)),
+                Vec3::new(2. * (x * y + z * w), 1. - 2. * (x * x + z * z), 2. * (y * z - x * w)),
+                Vec3::new(2. * (x * z - y * w), 2. * (y * z + x * w), 1. - 2. * (x * x + y * y)),
+            ],
+        }
+    }
+
+    pub fn rotate(&self, v: Vec3) -> Vec3 {
+        self.to_mat3().mul_vec(v)
+    }
+}
+
+impl EntityPosition {
+    /// Position in world units, whichever way it was stored
+    pub fn to_world(&self) -> Vec3 {
+        match self {
+            EntityPosition::Fixed(position) => Vec3::from_fixed(position),
+            EntityPosition::Float(position) => Vec3::from(*position),
+        }
+    }
+}
+
+impl UpdateOrientationData {
+    pub fn to_mat3(&self) -> Mat3 {
+        Mat3::from_fixed(&self.a, &self.b, &self.c)
+    }
+}
+
+impl EntityState {
+    pub fn world_position(&self) -> Option<Vec3> {
+        self.position.as_ref().map(EntityPosition::to_world)
+    }
+
+    pub fn rotation(&self) -> Option<Quat> {
+        self.orientation.as_ref().map(|orientation| orientation.to_mat3().to_quat())
+    }
+}
+
+impl DaggerData {
+    pub fn orientation(&self) -> Mat3 {
+        Mat3::from_fixed(&self.orientationa, &self.orientationb, &self.orientationc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: Vec3, b: Vec3) -> bool {
+        a.distance(b) < 1e-3
+    }
+
+    #[test]
+    fn positions_share_one_space() {
+        let fixed = EntityPosition::Fixed([160, -32, 8]).to_world();
+        let float = EntityPosition::Float([10., -2., 0.5]).to_world();
+        assert_eq!(fixed, float);
+        assert_eq!(Vec3::new(3., 0., 4.).distance(Vec3::ZERO), 5.);
+        assert_eq!(Vec3::new(3., 7., 4.).distance_xz(Vec3::ZERO), 5.);
+    }
+
+    #[test]
+    fn orientations_convert_to_quaternions() {
+        let max = i16::MAX;
+        let identity = UpdateOrientationData { a: [max, 0, 0], b: [0, max, 0], c: [0, 0, max] };
+        assert_eq!(identity.to_mat3(), Mat3::IDENTITY);
+        assert_eq!(identity.to_mat3().to_quat(), Quat::IDENTITY);
+
+        for i in 0..64 {
+            let angle = i as f32 * 0.2 - 6.;
+            let axis = Vec3::new(angle.cos(), 1.5, angle.sin() - 0.3);
+            let q = Quat::from_axis_angle(axis, angle);
+            let m = q.to_mat3();
+            let row = |v: Vec3| [v.x, v.y, v.z].map(|x| (x * ORIENTATION_SCALE).round() as i16);
+            let stored = UpdateOrientationData { a: row(m.rows[0]), b: row(m.rows[1]), c: row(m.rows[2]) };
+
+            let decoded = stored.to_mat3().to_quat();
+            let v = Vec3::new(1., 2., 3.);
+            assert!(close(decoded.rotate(v), q.rotate(v)));
+            assert!(close(stored.to_mat3().mul_vec(v), m.mul_vec(v)));
+        }
+        assert!(close(Quat::from_yaw(std::f32::consts::FRAC_PI_2).rotate(Vec3::new(1., 0., 0.)), Vec3::new(0., 0., -1.)));
+    }
+}
@@ -0,0 +1,132 @@
+//
+// comparing two runs of the same spawnset
+//
+
+use crate::models::StatsFrame;
+use super::*;
+
+/// Differences at the end of one second, positive values mean the run is ahead of the ghost
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GhostDelta {
+    pub second: usize,
+    pub gems: i32,
+    pub kills: i32,
+    pub homing: i32,
+    pub enemies_alive: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LevelSplit {
+    pub level: u8,
+    pub run: Option<f32>,
+    pub ghost: Option<f32>,
+}
+
+/// A run overlaid on a ghost run of the same spawnset.
+///
+/// `seconds` only covers the seconds both runs reached, `run_frames` and
+/// `ghost_frames` keep the full series for whatever comes after.
+#[derive(Debug, Clone)]
+pub struct GhostComparison {
+    pub seconds: Vec<GhostDelta>,
+    pub level_splits: Vec<LevelSplit>,
+    pub run_frames: Vec<StatsFrame>,
+    pub ghost_frames: Vec<StatsFrame>,
+}
+
+impl GhostDelta {
+    fn between(second: usize, run: &StatsFrame, ghost: &StatsFrame) -> Self {
+        Self {
+            second,
+            gems: run.gems_collected - ghost.gems_collected,
+            kills: run.kills - ghost.kills,
+            homing: run.homing - ghost.homing,
+            enemies_alive: run.enemies_alive - ghost.enemies_alive,
+        }
+    }
+}
+
+impl LevelSplit {
+    /// How much later the run levelled up than the ghost, negative if it was earlier
+    pub fn delta(&self) -> Option<f32> {
+        Some(self.run? - self.ghost?)
+    }
+}
+
+impl GhostComparison {
+    /// Frames are `ReplayData::stats_frames` series, progressions are only used for the level up times
+    pub fn new(run_frames: Vec<StatsFrame>, run: &Progression, ghost_frames: Vec<StatsFrame>, ghost: &Progression) -> Self {
+        let seconds = run_frames.iter()
+            .zip(&ghost_frames)
+            .enumerate()
+            .map(|(i, (run, ghost))| GhostDelta::between(i + 1, run, ghost))
+            .collect();
+        let level_splits = (2..=4)
+            .map(|level| LevelSplit { level, run: run.level_up_time(level), ghost: ghost.level_up_time(level) })
+            .collect();
+
+        Self { seconds, level_splits, run_frames, ghost_frames }
+    }
+
+    /// Delta at the end of `second`, counted from 1 like `GhostDelta::second`
+    pub fn at_second(&self, second: usize) -> Option<&GhostDelta> {
+        self.seconds.get(second.checked_sub(1)?)
+    }
+
+    pub fn level_split(&self, level: u8) -> Option<&LevelSplit> {
+        self.level_splits.iter().find(|split| split.level == level)
+    }
+}
+
+impl DdRpl {
+    /// Compares this run against `ghost`, both must have been recorded on the same spawnset
+    pub fn compare_to(&mut self, ghost: &mut DdRpl) -> Result<GhostComparison> {
+        if self.header.spawnset_hash != ghost.header.spawnset_hash {
+            bail!("Can't compare replays of different spawnsets ({} and {})", self.header.spawnset_hash, ghost.header.spawnset_hash);
+        }
+
+        let run_frames = self.stats()?.frames;
+        let run = self.progression()?;
+        let ghost_frames = ghost.stats()?.frames;
+        let ghost_progression = ghost.progression()?;
+        Ok(GhostComparison::new(run_frames, &run, ghost_frames, &ghost_progression))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::replay::tests::extend_test_data;
+
+    fn run(gem_every: usize, seconds: usize) -> ReplayData {
+        extend_test_data(seconds * 60, |frame| match frame % gem_every {
+            0 => vec![ReplayEvent::GemPickup],
+            _ => vec![],
+        })
+    }
+
+    #[test]
+    fn deltas_and_splits() {
+        let (fast, slow) = (run(2, 3), run(4, 2));
+        let comparison = GhostComparison::new(
+            fast.stats_frames(1, 0),
+            &fast.progression(None),
+            slow.stats_frames(1, 0),
+            &slow.progression(None),
+        );
+
+        assert_eq!(comparison.seconds.len(), 2);
+        assert_eq!(comparison.ghost_frames.len(), 2);
+        assert_eq!(comparison.run_frames.len(), 3);
+        assert_eq!(comparison.at_second(0), None);
+        assert_eq!(comparison.at_second(1), Some(&GhostDelta { second: 1, gems: 15, ..Default::default() }));
+        assert_eq!(comparison.at_second(2).unwrap().gems, 30);
+
+        // The fast run reaches level 2 with its 10th gem at frame 18, the slow one at frame 36
+        let split = comparison.level_split(2).unwrap();
+        assert_eq!((split.run, split.ghost), (Some(19. / 60.), Some(37. / 60.)));
+        assert!((split.delta().unwrap() + 0.3).abs() < 1e-6);
+        let split = comparison.level_split(3).unwrap();
+        assert!(split.run.is_some() && split.ghost.is_none() && split.delta().is_none());
+    }
+}
@@ -0,0 +1,146 @@
+//
+// player input tracks and analytics
+//
+
+use super::*;
+
+/// Input recorded at the end of one frame, the mouse values are the movement
+/// since the previous frame in raw mouse counts
+#[derive(Debug, Clone, PartialEq)]
+pub struct InputFrame {
+    pub frame: usize,
+    pub buttons: ButtonData,
+    pub mouse_dx: i16,
+    pub mouse_dy: i16,
+}
+
+/// Summary of a player's inputs over a replay.
+///
+/// A click is a press of a mouse button, counted on the frame it's released
+/// since that's the only edge the replay records. `turn_rate` is the
+/// horizontal mouse movement multiplied by the look speed, per second, so it
+/// compares turning speed between players with different sensitivities.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InputStats {
+    pub frames: usize,
+    pub seconds: f32,
+    pub look_speed: Option<f32>,
+    pub shoot_clicks: u32,
+    pub homing_clicks: u32,
+    pub shoot_held_seconds: f32,
+    pub jumps: u32,
+    pub strafe_seconds: f32,
+    pub moving_seconds: f32,
+    pub mouse_travel: f64,
+    pub turn_rate: f64,
+}
+
+impl InputFrame {
+    pub fn is_strafing(&self) -> bool {
+        self.buttons.left.is_held() != self.buttons.right.is_held()
+    }
+
+    pub fn is_moving(&self) -> bool {
+        self.is_strafing() || self.buttons.forward.is_held() != self.buttons.backwards.is_held()
+    }
+}
+
+impl InputStats {
+    pub fn shots_per_second(&self) -> f32 {
+        if self.seconds > 0. { self.shoot_clicks as f32 / self.seconds } else { 0. }
+    }
+
+    pub fn jumps_per_second(&self) -> f32 {
+        if self.seconds > 0. { self.jumps as f32 / self.seconds } else { 0. }
+    }
+}
+
+impl ReplayData {
+    /// Inputs of every frame that ended with an `EndFrame`, in order
+    pub fn input_timeline(&self) -> Vec<InputFrame> {
+        self.frames.iter()
+            .enumerate()
+            .flat_map(|(frame, replay_frame)| replay_frame.events.iter().map(move |event| (frame, event)))
+            .filter_map(|(frame, event)| match event {
+                ReplayEvent::EndFrame(buttons, mouse) => Some(InputFrame {
+                    frame,
+                    buttons: buttons.clone(),
+                    mouse_dx: mouse.x,
+                    mouse_dy: mouse.y,
+                }),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn look_speed(&self) -> Option<f32> {
+        self.frames.iter().flat_map(|frame| &frame.events).find_map(|event| match event {
+            ReplayEvent::EndFrame(_, mouse) => mouse.look_speed,
+            _ => None,
+        })
+    }
+
+    pub fn input_stats(&self) -> InputStats {
+        let timeline = self.input_timeline();
+        let per_frame = 1. / FRAMES_PER_SECOND as f32;
+        let look_speed = self.look_speed();
+        let mut stats = InputStats {
+            frames: timeline.len(),
+            seconds: timeline.len() as f32 * per_frame,
+            look_speed,
+            ..Default::default()
+        };
+
+        let mut horizontal_travel = 0.;
+        for input in &timeline {
+            match input.buttons.shoot {
+                MouseButtonState::Held => stats.shoot_held_seconds += per_frame,
+                MouseButtonState::Released => stats.shoot_clicks += 1,
+                _ => {},
+            }
+            if input.buttons.homing == MouseButtonState::Released {
+                stats.homing_clicks += 1;
+            }
+            if input.buttons.jump == JumpButtonState::JustPressed {
+                stats.jumps += 1;
+            }
+            if input.is_strafing() {
+                stats.strafe_seconds += per_frame;
+            }
+            if input.is_moving() {
+                stats.moving_seconds += per_frame;
+            }
+            let (dx, dy) = (input.mouse_dx as f64, input.mouse_dy as f64);
+            stats.mouse_travel += (dx * dx + dy * dy).sqrt();
+            horizontal_travel += dx.abs();
+        }
+
+        if stats.seconds > 0. {
+            stats.turn_rate = horizontal_travel * look_speed.unwrap_or(1.) as f64 / stats.seconds as f64;
+        }
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::models::replay::tests::test_data;
+
+    #[test]
+    fn input_tracks_and_stats() {
+        let data = test_data();
+        let timeline = data.input_timeline();
+        assert_eq!(timeline.len(), 2);
+        assert_eq!((timeline[1].frame, timeline[1].mouse_dx, timeline[1].mouse_dy), (1, -12, 7));
+        assert!(timeline[0].is_strafing() && timeline[0].is_moving());
+
+        let stats = data.input_stats();
+        assert_eq!(stats.frames, 2);
+        assert_eq!(stats.look_speed, Some(2.3));
+        assert_eq!((stats.jumps, stats.shoot_clicks, stats.homing_clicks), (2, 0, 2));
+        assert_eq!(stats.shoot_held_seconds, 2. / 60.);
+        assert!((stats.jumps_per_second() - 60.).abs() < 1e-3);
+        assert!((stats.mouse_travel - 2. * (193f64).sqrt()).abs() < 1e-9);
+        assert!((stats.turn_rate / (24. * 2.3 * 30.) - 1.).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,275 @@
+//
+// entity lifecycles derived from the event stream
+//
+
+use super::*;
+
+/// Enemy types as the game counts them, in the order of
+/// `StatsFrame::per_enemy_alive_count` and `per_enemy_kill_count`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EnemyType {
+    Skull1 = 0,
+    Skull2,
+    Skull3,
+    Spiderling,
+    Skull4,
+    Squid1,
+    Squid2,
+    Squid3,
+    Centipede,
+    Gigapede,
+    Spider1,
+    Spider2,
+    Leviathan,
+    Orb,
+    Thorn,
+    Ghostpede,
+    SpiderEgg,
+}
+
+/// Everything the event stream tells us about one spawned entity.
+///
+/// Replays don't record enemy deaths, the game works them out again from the
+/// recorded hits. `death_frame` does the same using `EnemyType::hp` and one
+/// point of damage per weak spot hit, so for enemies it's an estimate. For
+/// daggers it's the frame of their despawn event.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EntityLifecycle {
+    pub id: EntityId,
+    pub entity_type: EntityType,
+    pub enemy_type: Option<EnemyType>,
+    pub spawn_frame: usize,
+    pub death_frame: Option<usize>,
+    pub transmute_frames: Vec<usize>,
+    pub weak_spot_hits: u32,
+    pub armor_hits: u32,
+    pub final_position: Option<EntityPosition>,
+}
+
+/// Builds up entity lifecycles one event at a time, so it works with both
+/// `ReplayData` and `ReplayEventReader`.
+#[derive(Debug, Clone, Default)]
+pub struct LifecycleTracker {
+    pub entities: Vec<EntityLifecycle>,
+}
+
+/// The events that decide when entities come and go. `LifecycleTracker` and
+/// `ReplayState` both read them through `LifecycleEvent::from_event` and
+/// count hits with `count_weak_spot_hit`, so they agree on who is alive.
+#[derive(Debug, Clone, Copy)]
+pub(super) enum LifecycleEvent<'a> {
+    Spawn(&'a EntityData), // Gets the next id
+    Despawn(EntityId),
+    WeakSpotHit(EntityId),
+}
+
+impl EnemyType {
+    pub fn from_entity(entity: &EntityData) -> Option<Self> {
+        Some(match entity {
+            EntityData::Dagger(_) => return None,
+            EntityData::Squid1(_) => EnemyType::Squid1,
+            EntityData::Squid2(_) => EnemyType::Squid2,
+            EntityData::Squid3(_) => EnemyType::Squid3,
+            EntityData::Boid(boid) => match boid.boid_type {
+                BoidType::Skull1 => EnemyType::Skull1,
+                BoidType::Skull2 => EnemyType::Skull2,
+                BoidType::Skull3 => EnemyType::Skull3,
+                BoidType::Skull4 => EnemyType::Skull4,
+                BoidType::Spiderling => EnemyType::Spiderling,
+            },
+            EntityData::Centipede(_) => EnemyType::Centipede,
+            EntityData::Spider1(_) => EnemyType::Spider1,
+            EntityData::Spider2(_) => EnemyType::Spider2,
+            EntityData::Egg(_) => EnemyType::SpiderEgg,
+            EntityData::Leviathan(_) => EnemyType::Leviathan,
+            EntityData::Gigapede(_) => EnemyType::Gigapede,
+            EntityData::Thorn(_) => EnemyType::Thorn,
+            EntityData::Ghostpede(_) => EnemyType::Ghostpede,
+        })
+    }
+
+    /// Hit points, as documented by the community
+    pub fn hp(&self) -> u32 {
+        match self {
+            EnemyType::Skull1 => 1,
+            EnemyType::Skull2 => 5,
+            EnemyType::Skull3 => 10,
+            EnemyType::Spiderling => 3,
+            EnemyType::Skull4 => 100,
+            EnemyType::Squid1 => 10,
+            EnemyType::Squid2 => 20,
+            EnemyType::Squid3 => 90,
+            EnemyType::Centipede => 75,
+            EnemyType::Gigapede => 250,
+            EnemyType::Spider1 => 25,
+            EnemyType::Spider2 => 200,
+            EnemyType::Leviathan => 1500,
+            EnemyType::Orb => 2400,
+            EnemyType::Thorn => 120,
+            EnemyType::Ghostpede => 500,
+            EnemyType::SpiderEgg => 3,
+        }
+    }
+
+    /// Index into the per enemy arrays of `StatsFrame`
+    pub fn stats_index(&self) -> usize {
+        *self as usize
+    }
+}
+
+impl<'a> LifecycleEvent<'a> {
+    pub(super) fn from_event(event: &'a ReplayEvent) -> Option<Self> {
+        match event {
+            ReplayEvent::Spawn(entity) => Some(LifecycleEvent::Spawn(entity)),
+            ReplayEvent::DaggerDewspawn(despawn) => Some(LifecycleEvent::Despawn(despawn.dagger_id)),
+            ReplayEvent::EnemyHitWeakSpot(hit) => Some(LifecycleEvent::WeakSpotHit(hit.enemy_id)),
+            _ => None,
+        }
+    }
+}
+
+/// Counts one weak spot hit, true once an enemy has taken as many as its hp
+pub(super) fn count_weak_spot_hit(enemy_type: Option<EnemyType>, weak_spot_hits: &mut u32) -> bool {
+    *weak_spot_hits += 1;
+    enemy_type.is_some_and(|enemy_type| *weak_spot_hits >= enemy_type.hp())
+}
+
+impl EntityLifecycle {
+    pub fn is_alive_at(&self, frame: usize) -> bool {
+        let before_death = match self.death_frame {
+            Some(death) => frame < death,
+            None => true,
+        };
+        self.spawn_frame <= frame && before_death
+    }
+
+    /// Number of frames between spawning and dying, `None` if it outlived the replay
+    pub fn lifetime_frames(&self) -> Option<usize> {
+        self.death_frame.map(|death| death - self.spawn_frame)
+    }
+}
+
+impl LifecycleTracker {
+    pub fn get(&self, id: EntityId) -> Option<&EntityLifecycle> {
+        id.checked_sub(1).and_then(|i| usize::try_from(i).ok()).and_then(|i| self.entities.get(i))
+    }
+
+    fn get_mut(&mut self, id: EntityId) -> Option<&mut EntityLifecycle> {
+        id.checked_sub(1).and_then(|i| usize::try_from(i).ok()).and_then(|i| self.entities.get_mut(i))
+    }
+
+    pub fn apply(&mut self, frame: usize, event: &ReplayEvent) {
+        match LifecycleEvent::from_event(event) {
+            Some(LifecycleEvent::Spawn(entity)) => {
+                self.entities.push(EntityLifecycle {
+                    id: self.entities.len() as EntityId + 1,
+                    entity_type: entity.entity_type(),
+                    enemy_type: EnemyType::from_entity(entity),
+                    spawn_frame: frame,
+                    death_frame: None,
+                    transmute_frames: vec![],
+                    weak_spot_hits: 0,
+                    armor_hits: 0,
+                    final_position: entity.position(),
+                });
+            },
+            Some(LifecycleEvent::Despawn(id)) => {
+                if let Some(dagger) = self.get_mut(id) {
+                    dagger.death_frame.get_or_insert(frame);
+                }
+            },
+            Some(LifecycleEvent::WeakSpotHit(id)) => {
+                if let Some(enemy) = self.get_mut(id) {
+                    if count_weak_spot_hit(enemy.enemy_type, &mut enemy.weak_spot_hits) {
+                        enemy.death_frame.get_or_insert(frame);
+                    }
+                }
+            },
+            None => match event {
+                ReplayEvent::UpdateEntityPosition(id, position) => {
+                    if let Some(entity) = self.get_mut(*id) {
+                        entity.final_position = Some(EntityPosition::Fixed(*position));
+                    }
+                },
+                ReplayEvent::Transmute(id, _) => {
+                    if let Some(entity) = self.get_mut(*id) {
+                        entity.transmute_frames.push(frame);
+                    }
+                },
+                ReplayEvent::EnemyHitArmor(hit) => {
+                    if let Some(enemy) = self.get_mut(hit.enemy_id) {
+                        enemy.armor_hits += 1;
+                    }
+                },
+                _ => {},
+            },
+        }
+    }
+}
+
+impl ReplayData {
+    /// Lifecycle of every spawned entity, indexed by `id - 1`
+    pub fn entity_lifecycles(&self) -> Vec<EntityLifecycle> {
+        let mut tracker = LifecycleTracker::default();
+        for (frame, replay_frame) in self.frames.iter().enumerate() {
+            for event in &replay_frame.events {
+                tracker.apply(frame, event);
+            }
+        }
+        tracker.entities
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::replay::tests::test_data;
+
+    #[test]
+    fn tracks_spawns_hits_and_deaths() {
+        let lifecycles = test_data().entity_lifecycles();
+        assert_eq!(lifecycles.len(), 3);
+
+        let squid = &lifecycles[0];
+        assert_eq!(squid.enemy_type, Some(EnemyType::Squid1));
+        assert_eq!((squid.weak_spot_hits, squid.armor_hits), (1, 1));
+        assert_eq!(squid.transmute_frames, vec![1]);
+        assert_eq!(squid.final_position, Some(EntityPosition::Fixed([4, 5, 6])));
+        assert_eq!(squid.death_frame, None);
+
+        let dagger = &lifecycles[1];
+        assert_eq!(dagger.enemy_type, None);
+        assert_eq!(dagger.death_frame, Some(1));
+        assert_eq!(dagger.lifetime_frames(), Some(1));
+        assert!(dagger.is_alive_at(0) && !dagger.is_alive_at(1));
+
+        let skull = &lifecycles[2];
+        assert_eq!(skull.enemy_type, Some(EnemyType::Skull2));
+        assert_eq!(skull.final_position, Some(EntityPosition::Fixed([100, 200, 300])));
+    }
+
+    #[test]
+    fn enemies_die_once_out_of_hp() {
+        let hit = ReplayEvent::EnemyHitWeakSpot(EnemyHitData { enemy_id: 3, dagger_id: 2, segment: 0 });
+        let mut data = test_data();
+        data.frames[1].events.splice(0..0, vec![hit.clone(); 4]);
+        data.frames[2].events.insert(0, hit.clone());
+        data.frames[2].events.insert(0, hit);
+
+        let skull = &data.entity_lifecycles()[2];
+        assert_eq!(skull.weak_spot_hits, 6);
+        assert_eq!(skull.death_frame, Some(2));
+    }
+
+    #[test]
+    fn ignores_out_of_range_ids() {
+        let mut data = test_data();
+        data.frames[1].events.splice(0..0, [
+            ReplayEvent::UpdateEntityPosition(i32::MIN, [0, 0, 0]),
+            ReplayEvent::EnemyHitArmor(EnemyHitData { enemy_id: i32::MIN.wrapping_neg(), dagger_id: 2, segment: 0 }),
+            ReplayEvent::Transmute(0, TransmuteData { a: [0; 3], b: [0; 3], c: [0; 3], d: [0; 3] }),
+        ]);
+
+        assert_eq!(data.entity_lifecycles(), test_data().entity_lifecycles());
+    }
+}
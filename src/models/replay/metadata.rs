@@ -0,0 +1,138 @@
+//
+// player identity and timestamp rewriting
+//
+
+use super::*;
+
+/// Replacement values for the identifying fields of a replay, fields left as
+/// `None` keep their current value
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MetadataRewrite {
+    pub player_name: Option<String>,
+    pub player_id: Option<i32>,
+    pub recorded_at: Option<SystemTime>,
+}
+
+impl MetadataRewrite {
+    /// Strips the player's name and id and sets the timestamp to the release of the game
+    pub fn anonymous() -> Self {
+        Self {
+            player_name: Some(String::new()),
+            player_id: Some(0),
+            recorded_at: Some(UNIX_EPOCH + Duration::from_secs(DD_RELEASE_TIMESTAMP)),
+        }
+    }
+
+    /// Like `anonymous`, but the player gets a stable made up name and id
+    /// derived from `salt` and their real id, so replays of the same player
+    /// can still be grouped together without knowing who they are
+    pub fn pseudonymous(player_id: i32, salt: &[u8]) -> Self {
+        let mut input = salt.to_vec();
+        input.extend_from_slice(&player_id.to_le_bytes());
+        let digest = md5::compute(&input);
+        let id = i32::from_le_bytes([digest[0], digest[1], digest[2], digest[3]]) & i32::MAX;
+        Self {
+            player_name: Some(format!("player-{}", crate::utils::md5_to_string_lower(&digest[..4]))),
+            player_id: Some(id),
+            ..Self::anonymous()
+        }
+    }
+}
+
+impl DdRplHeader {
+    pub fn rewrite_metadata(&mut self, rewrite: &MetadataRewrite) {
+        if let Some(player_name) = &rewrite.player_name {
+            self.player_name = player_name.clone();
+        }
+        if let Some(player_id) = rewrite.player_id {
+            self.player_id = player_id;
+        }
+        if let Some(recorded_at) = rewrite.recorded_at {
+            self.recorded_at = recorded_at;
+        }
+    }
+}
+
+impl DfRpl2Header {
+    /// `DF_RPL2` only stores the player's name, the other fields are ignored
+    pub fn rewrite_metadata(&mut self, rewrite: &MetadataRewrite) {
+        if let Some(player_name) = &rewrite.player_name {
+            self.player_name = player_name.clone();
+        }
+    }
+}
+
+impl DdRpl {
+    /// Rewrites the header, the compressed events are written back untouched
+    pub fn rewrite_metadata(&mut self, rewrite: &MetadataRewrite) {
+        self.header.rewrite_metadata(rewrite);
+    }
+
+    /// Also zeroes `unknown`, nobody knows what those bytes hold so they
+    /// could identify the player
+    pub fn anonymize(&mut self) {
+        self.rewrite_metadata(&MetadataRewrite::anonymous());
+        self.header.unknown = [0; 10];
+    }
+}
+
+impl DfRpl2 {
+    pub fn rewrite_metadata(&mut self, rewrite: &MetadataRewrite) {
+        self.header.rewrite_metadata(rewrite);
+    }
+
+    /// Also zeroes `funny_bytes` for the same reason as `DdRpl::anonymize`,
+    /// their length is kept
+    pub fn anonymize(&mut self) {
+        self.rewrite_metadata(&MetadataRewrite::anonymous());
+        self.header.funny_bytes.fill(0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::replay::tests::{test_data, test_replay};
+
+    #[test]
+    fn anonymized_replays_keep_their_events() {
+        let mut replay = test_replay();
+        let payload = replay.compressed_data.clone();
+        replay.anonymize();
+
+        let mut written = vec![];
+        replay.write_to(&mut written).unwrap();
+        let parsed = DdRpl::from_reader(&mut &written[..]).unwrap();
+        assert_eq!(parsed.header.player_name, "");
+        assert_eq!(parsed.header.player_id, 0);
+        assert_eq!(parsed.header.recorded_at, UNIX_EPOCH + Duration::from_secs(DD_RELEASE_TIMESTAMP));
+        assert_eq!(parsed.header.unknown, [0; 10]);
+        assert_eq!(parsed.compressed_data, payload);
+
+        let mut replay = test_replay();
+        replay.rewrite_metadata(&MetadataRewrite { player_id: Some(1), ..Default::default() });
+        assert_eq!((replay.header.player_id, replay.header.player_name.as_str()), (1, "xvlv"));
+    }
+
+    #[test]
+    fn pseudonyms_are_stable() {
+        let a = MetadataRewrite::pseudonymous(21854, b"salt");
+        assert_eq!(a, MetadataRewrite::pseudonymous(21854, b"salt"));
+        assert_ne!(a.player_id, MetadataRewrite::pseudonymous(21855, b"salt").player_id);
+        assert_ne!(a.player_id, MetadataRewrite::pseudonymous(21854, b"pepper").player_id);
+        assert!(a.player_id.unwrap() >= 0);
+        assert_eq!(a.player_name.as_ref().unwrap().len(), "player-".len() + 8);
+    }
+
+    #[test]
+    fn dfrpl2_rewrites_the_name() {
+        let mut replay = DfRpl2::new(DfRpl2Header { player_name: "xvlv".into(), funny_bytes: vec![1, 2, 3] }, test_data());
+        replay.rewrite_metadata(&MetadataRewrite::pseudonymous(21854, b"salt"));
+        assert!(replay.header.player_name.starts_with("player-"));
+        assert_eq!(replay.header.funny_bytes, vec![1, 2, 3]);
+        replay.anonymize();
+        assert_eq!(replay.header.player_name, "");
+        assert_eq!(replay.header.funny_bytes, vec![0, 0, 0]);
+        assert_eq!(*replay.data(), test_data());
+    }
+}
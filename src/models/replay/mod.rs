@@ -0,0 +1,1219 @@
+//
+// replay file models and utils
+//
+
+use std::{io::{Read, Seek, SeekFrom, Write}, time::{SystemTime, Duration, UNIX_EPOCH}};
+use anyhow::{Result, bail};
+use num_derive::FromPrimitive;
+
+use super::spawnset::AnySpawnset;
+
+mod convert;
+mod damage;
+mod death;
+mod decoder;
+mod encoder;
+mod error;
+mod geometry;
+mod ghost;
+mod input;
+mod lifecycle;
+mod metadata;
+mod progression;
+mod state;
+mod stats;
+mod trim;
+mod validate;
+
+pub use convert::{open_any_replay, AnyReplay};
+pub use damage::{DamageBreakdown, EnemyDamage, HitCounts};
+pub use death::{DeathReport, DeathType, NearbyEnemy};
+pub use decoder::ReplayEventReader;
+pub use error::ReplayError;
+pub use geometry::{Mat3, Quat, Vec3, ORIENTATION_SCALE, POSITION_SCALE};
+pub use ghost::{GhostComparison, GhostDelta, LevelSplit};
+pub use input::{InputFrame, InputStats};
+pub use lifecycle::{EnemyType, EntityLifecycle, LifecycleTracker};
+pub use metadata::MetadataRewrite;
+pub use progression::{LevelUp, Progression, ProgressionFrame};
+pub use state::{EntityState, ReplayState};
+pub use stats::HeaderStats;
+pub use validate::{ConsistencyOptions, ConsistencyReport, HeaderField, StatMismatch};
+use decoder::OffsetReader;
+
+type EntityId = i32;
+type PositionInt = [i16; 3];
+type PositionFloat = [f32; 3];
+type LeviathanData = i32;
+
+// Replay timestamps are stored as seconds since the game's release (2016-02-18)
+const DD_RELEASE_TIMESTAMP: u64 = 1455753600;
+
+// The first frame stores the raw mouse sensitivity, scaled by this on read
+const LOOK_SPEED_SCALE: f32 = 500. / 3.;
+
+// Every event stream frame is one tick of the game
+const FRAMES_PER_SECOND: usize = 60;
+
+const MAX_COMPRESSED_DATA_LEN: u32 = 40000000;
+
+/// A `DF_RPL2` replay. The payload it was read with is written back as is
+/// until the events are changed through `DfRpl2::data_mut`.
+#[derive(Debug, Clone)]
+pub struct DfRpl2 {
+    pub header: DfRpl2Header,
+    data: ReplayData,
+    compressed_data: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplayData {
+    pub frames: Vec<ReplayFrame>,
+    pub entities: Vec<Entity>,
+    pub trailing_bytes: Vec<u8>, // Anything found after the end of the replay
+}
+
+#[derive(Debug, Clone)]
+pub struct DfRpl2Header {
+    pub player_name: String,
+    pub funny_bytes: Vec<u8>
+}
+
+#[derive(Debug, Clone)]
+pub struct DdRpl {
+    pub header: DdRplHeader,
+    pub compressed_data: Option<Vec<u8>>,
+    pub data: Option<ReplayData>,
+    pub extra: Option<ExtraData>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ExtraData {
+    pub homing: Vec<i32>,
+    pub homing_used: Vec<i32>,
+    pub starting_gems: i32,
+    pub starting_hand: u8,
+    pub look_speed: f32,
+    pub lvl2_time: f32,
+    pub lvl3_time: f32,
+    pub lvl4_time: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct DdRplHeader {
+    pub file_version: u32,
+    pub recorded_at: SystemTime,
+    pub time: f32,
+    pub starting_time: f32,
+    pub daggers_fired: u32,
+    pub death_type: i32,
+    pub gems: u32,
+    pub kills: u32,
+    pub daggers_hit: u32,
+    pub player_name: String,
+    pub player_id: i32,
+    pub unknown: [u8; 10],
+    pub spawnset_bin: Vec<u8>,
+    pub spawnset_hash: String,
+    pub compressed_data_len: u32,
+    pub spawnset: Option<AnySpawnset>
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entity {
+    pub id: EntityId,
+    pub entity_type: EntityType,
+}
+
+// Daggers and boids use fixed point positions, other enemies spawn with floats
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EntityPosition {
+    Fixed(PositionInt),
+    Float(PositionFloat),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplayFrame {
+    pub events: Vec<ReplayEvent>
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReplayEvent {
+    Spawn(EntityData),
+    UpdateEntityPosition(EntityId, PositionInt),
+    UpdateEntityOrientation(EntityId, UpdateOrientationData),
+    UpdateEntityTarget(EntityId, [i16; 3]),
+    DaggerDewspawn(DaggerDespawnData),
+    EnemyHitWeakSpot(EnemyHitData),
+    EnemyHitArmor(EnemyHitData),
+    PlayerDeath(PlayerDeathData),
+    GemPickup,
+    Transmute(EntityId, TransmuteData),
+    EndFrame(ButtonData, MouseData),
+    EndReplay,
+    // Unrecognised event type, the size of its payload is unknown so it holds
+    // the rest of the stream and ends the replay
+    Unknown(u8, Vec<u8>),
+}
+
+#[derive(Debug, Clone, Copy, FromPrimitive, PartialEq, Eq)]
+pub enum EntityType {
+    Dagger = 0x1,
+    Squid1 = 0x3,
+    Squid2 = 0x4,
+    Squid3 = 0x5,
+    Boid = 0x6, // Skulls and Spiderlings
+    Centipede = 0x7,
+    Spider1 = 0x8,
+    Spider2 = 0x9,
+    Egg = 0xA,
+    Leviathan = 0xB,
+    Gigapede = 0xC,
+    Thorn = 0xD,
+    Ghostpede = 0xF,
+}
+
+// Fields named `a`, `b` and `funnyN` haven't been figured out yet, they are
+// kept exactly as read so replays re-encode without losing anything
+#[derive(Debug, Clone, PartialEq)]
+pub enum EntityData {
+    Dagger(DaggerData),
+    Squid1(SquidData),
+    Squid2(SquidData),
+    Squid3(SquidData),
+    Boid(BoidData), // Skulls and Spiderlings
+    Centipede(PedeData),
+    Spider1(SpiderData),
+    Spider2(SpiderData),
+    Egg(EggData),
+    Leviathan(LeviathanData),
+    Gigapede(PedeData),
+    Thorn(ThornData),
+    Ghostpede(PedeData),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ThornData {
+    pub a: i32,
+    pub position: PositionFloat,
+    pub rotation: f32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EggData {
+    pub spider_spawner: EntityId,
+    pub funny1: [f32; 3],
+    pub funny2: [f32; 3],
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpiderData {
+    pub a: i32,
+    pub position: PositionFloat,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PedeData {
+    pub a: i32,
+    pub position: PositionFloat,
+    pub b: [f32; 3],
+    pub funny1: [f32; 3],
+    pub funny2: [f32; 3],
+    pub funny3: [f32; 3],
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BoidData {
+    pub boid_type: BoidType,
+    pub spanwer: EntityId,
+    pub position: PositionInt,
+    pub funny1: [i16; 3],
+    pub funny2: [i16; 3],
+    pub funny3: [i16; 3],
+    pub funny4: [f32; 3],
+    pub speed: f32,
+}
+
+#[derive(Debug, Clone, FromPrimitive, PartialEq)]
+pub enum BoidType {
+    Skull1 = 1,
+    Skull2 = 2,
+    Skull3 = 3,
+    Skull4 = 5,
+    Spiderling = 4,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DaggerData {
+    pub a: i32,
+    pub position: PositionInt,
+    pub orientationa: [i16; 3],
+    pub orientationb: [i16; 3],
+    pub orientationc: [i16; 3],
+    pub b: u8,
+    pub dagger_level: DaggerLevel,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SquidData {
+    pub a: i32,
+    pub position: PositionFloat,
+    pub b: [f32; 3],
+    pub rotation: f32, // Radians
+}
+
+#[derive(Debug, Clone, FromPrimitive, PartialEq)]
+pub enum DaggerLevel {
+    Level0 = 0,
+    Level1,
+    Level2,
+    Level3,
+    Level4,
+    Level5,
+    Level6,
+    Level7,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct UpdateOrientationData {
+    pub a: [i16; 3],
+    pub b: [i16; 3],
+    pub c: [i16; 3],
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DaggerDespawnData {
+    pub dagger_id: i32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlayerDeathData {
+    pub death_type: i32,
+    pub unknown: i32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnemyHitData {
+    pub enemy_id: i32,
+    pub dagger_id: i32,
+    pub segment: i32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransmuteData {
+    pub a: [i16; 3],
+    pub b: [i16; 3],
+    pub c: [i16; 3],
+    pub d: [i16; 3],
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ButtonData {
+    pub left: KeyState,
+    pub right: KeyState,
+    pub forward: KeyState,
+    pub backwards: KeyState,
+    pub jump: JumpButtonState,
+    pub shoot: MouseButtonState,
+    pub homing: MouseButtonState,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum KeyState {
+    NotPressed,
+    Held,
+    Unknown(u8),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum JumpButtonState {
+    NotPressed,
+    Held,
+    JustPressed,
+    Unknown(u8),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MouseButtonState {
+    NotPressed,
+    Held,
+    Released,
+    Unknown(u8),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MouseData {
+    pub x: i16,
+    pub y: i16,
+    pub look_speed: Option<f32>,
+}
+
+impl ReplayData {
+    pub fn from_reader<R: Read>(source: &mut R) -> Result<Self, ReplayError> {
+        let mut reader = ReplayEventReader::new(source)?;
+        let mut entities: Vec<Entity> = vec![];
+        let mut frames: Vec<ReplayFrame> = vec![];
+        let mut current_frame: Vec<ReplayEvent> = vec![];
+
+        for item in &mut reader {
+            let (_frame, event) = item?;
+            if let ReplayEvent::Spawn(entity) = &event {
+                entities.push(Entity {
+                    id: entities.len() as EntityId + 1,
+                    entity_type: entity.entity_type(),
+                });
+            }
+
+            let ends_frame = matches!(event, ReplayEvent::EndFrame(_, _) | ReplayEvent::EndReplay | ReplayEvent::Unknown(_, _));
+            current_frame.push(event);
+            if ends_frame {
+                frames.push(ReplayFrame {
+                    events: std::mem::take(&mut current_frame),
+                });
+            }
+        }
+
+        let trailing_bytes = reader.read_trailing()?;
+
+        Ok(Self {
+            frames,
+            entities,
+            trailing_bytes,
+        })
+    }
+}
+
+impl EntityData {
+    pub fn entity_type(&self) -> EntityType {
+        match self {
+            EntityData::Dagger(_) => EntityType::Dagger,
+            EntityData::Squid1(_) => EntityType::Squid1,
+            EntityData::Squid2(_) => EntityType::Squid2,
+            EntityData::Squid3(_) => EntityType::Squid3,
+            EntityData::Boid(_) => EntityType::Boid,
+            EntityData::Centipede(_) => EntityType::Centipede,
+            EntityData::Spider1(_) => EntityType::Spider1,
+            EntityData::Spider2(_) => EntityType::Spider2,
+            EntityData::Egg(_) => EntityType::Egg,
+            EntityData::Leviathan(_) => EntityType::Leviathan,
+            EntityData::Gigapede(_) => EntityType::Gigapede,
+            EntityData::Thorn(_) => EntityType::Thorn,
+            EntityData::Ghostpede(_) => EntityType::Ghostpede,
+        }
+    }
+
+    pub fn position(&self) -> Option<EntityPosition> {
+        match self {
+            EntityData::Dagger(dagger) => Some(EntityPosition::Fixed(dagger.position)),
+            EntityData::Boid(boid) => Some(EntityPosition::Fixed(boid.position)),
+            EntityData::Squid1(squid) | EntityData::Squid2(squid) | EntityData::Squid3(squid) => Some(EntityPosition::Float(squid.position)),
+            EntityData::Centipede(pede) | EntityData::Gigapede(pede) | EntityData::Ghostpede(pede) => Some(EntityPosition::Float(pede.position)),
+            EntityData::Spider1(spider) | EntityData::Spider2(spider) => Some(EntityPosition::Float(spider.position)),
+            EntityData::Thorn(thorn) => Some(EntityPosition::Float(thorn.position)),
+            EntityData::Egg(_) | EntityData::Leviathan(_) => None,
+        }
+    }
+}
+
+impl KeyState {
+    pub fn is_held(&self) -> bool {
+        *self == KeyState::Held
+    }
+}
+
+impl std::convert::From<u8> for KeyState {
+    fn from(v: u8) -> Self {
+        match v {
+            0 => KeyState::NotPressed,
+            1 => KeyState::Held,
+            _ => KeyState::Unknown(v),
+        }
+    }
+}
+
+impl std::convert::From<&KeyState> for u8 {
+    fn from(v: &KeyState) -> Self {
+        match v {
+            KeyState::NotPressed => 0,
+            KeyState::Held => 1,
+            KeyState::Unknown(v) => *v,
+        }
+    }
+}
+
+impl std::convert::From<u8> for JumpButtonState {
+    fn from(v: u8) -> Self {
+        match v {
+            0 => JumpButtonState::NotPressed,
+            1 => JumpButtonState::Held,
+            2 => JumpButtonState::JustPressed,
+            _ => JumpButtonState::Unknown(v),
+        }
+    }
+}
+
+impl std::convert::From<&JumpButtonState> for u8 {
+    fn from(v: &JumpButtonState) -> Self {
+        match v {
+            JumpButtonState::NotPressed => 0,
+            JumpButtonState::Held => 1,
+            JumpButtonState::JustPressed => 2,
+            JumpButtonState::Unknown(v) => *v,
+        }
+    }
+}
+
+impl std::convert::From<u8> for MouseButtonState {
+    fn from(v: u8) -> Self {
+        match v {
+            0 => MouseButtonState::NotPressed,
+            1 => MouseButtonState::Held,
+            2 => MouseButtonState::Released,
+            _ => MouseButtonState::Unknown(v),
+        }
+    }
+}
+
+impl std::convert::From<&MouseButtonState> for u8 {
+    fn from(v: &MouseButtonState) -> Self {
+        match v {
+            MouseButtonState::NotPressed => 0,
+            MouseButtonState::Held => 1,
+            MouseButtonState::Released => 2,
+            MouseButtonState::Unknown(v) => *v,
+        }
+    }
+}
+
+impl DdRplHeader {
+    /// Reads only the header, seeking over the compressed events. Fails if
+    /// the payload is cut short or followed by anything.
+    pub fn read_from<R: Read + Seek>(source: &mut R) -> Result<Self, ReplayError> {
+        let mut r = OffsetReader::new(&mut *source);
+        let header = DdRplHeader::read_fields(&mut r)?;
+        let payload_start = r.offset;
+
+        let start = source.stream_position()?;
+        let end = source.seek(SeekFrom::End(0))?;
+        let payload_len = end.saturating_sub(start);
+        if payload_len < header.compressed_data_len as u64 {
+            return Err(ReplayError::UnexpectedEof { offset: payload_start + payload_len, frame: None, event_type: None });
+        }
+        if payload_len > header.compressed_data_len as u64 {
+            return Err(ReplayError::TrailingData { offset: payload_start + header.compressed_data_len as u64 });
+        }
+
+        Ok(header)
+    }
+
+    // Everything up to and including the compressed data length
+    fn read_fields<R: Read>(r: &mut OffsetReader<R>) -> Result<Self, ReplayError> {
+        let magic = r.read_bytes(6)?;
+        if magic != b"ddrpl." {
+            return Err(ReplayError::InvalidMagic { found: magic });
+        }
+        let file_version = r.read_u32()?;
+        let offset = r.offset;
+        let timestamp = r.read_u64()?;
+        let timestamp = DD_RELEASE_TIMESTAMP.checked_add(timestamp)
+            .and_then(|secs| UNIX_EPOCH.checked_add(Duration::from_secs(secs)))
+            .ok_or(ReplayError::InvalidTimestamp { offset, value: timestamp })?;
+        let time = r.read_f32()?;
+        let starting_time = r.read_f32()?;
+        let daggers_fired = r.read_u32()?;
+        let death_type = r.read_i32()?;
+        let gems_collected = r.read_u32()?;
+        let daggers_hit = r.read_u32()?;
+        let kills = r.read_u32()?;
+        let player_id = r.read_i32()?;
+        let username_len = r.read_u32()?;
+        let offset = r.offset;
+        let username = String::from_utf8(r.read_bytes(username_len as u64)?)
+            .map_err(|_| ReplayError::InvalidUsername { offset })?;
+        let unknown = r.read_array()?;
+        let hash_offset = r.offset;
+        let spawnset_hash = crate::utils::md5_to_string_lower(&r.read_array::<16>()?);
+        let spawnset_len = r.read_u32()?;
+        let spawnset_bin = r.read_bytes(spawnset_len as u64)?;
+        let found = spawnset_md5(&spawnset_bin);
+        if found != spawnset_hash {
+            return Err(ReplayError::SpawnsetHashMismatch { offset: hash_offset, expected: spawnset_hash, found });
+        }
+        let offset = r.offset;
+        let compressed_data_len = r.read_u32()?;
+
+        if compressed_data_len > MAX_COMPRESSED_DATA_LEN {
+            return Err(ReplayError::DataTooLarge { offset, len: compressed_data_len });
+        }
+
+        Ok(DdRplHeader {
+            player_name: username,
+            player_id,
+            unknown,
+            spawnset_hash,
+            kills,
+            death_type,
+            daggers_hit,
+            daggers_fired,
+            file_version,
+            time,
+            starting_time,
+            gems: gems_collected,
+            recorded_at: timestamp,
+            spawnset_bin,
+            compressed_data_len,
+            spawnset: None,
+        })
+    }
+
+    /// Decodes `spawnset_bin` into `spawnset`, the enemy version is picked
+    /// from the spawnset's header
+    pub fn create_spawnset(&mut self) -> Result<()> {
+        self.spawnset = Some(AnySpawnset::deserialize(&mut &self.spawnset_bin[..])?);
+        Ok(())
+    }
+
+    /// Whether `spawnset_hash` is the md5 of `spawnset_bin`, always true for
+    /// parsed headers but not after editing either of them
+    pub fn verify_spawnset_hash(&self) -> bool {
+        spawnset_md5(&self.spawnset_bin) == self.spawnset_hash
+    }
+}
+
+pub(crate) fn spawnset_md5(spawnset_bin: &[u8]) -> String {
+    crate::utils::md5_to_string_lower(&md5::compute(spawnset_bin)[..])
+}
+
+impl DdRpl {
+    /// Decodes `compressed_data` into `data`. The compressed events are kept
+    /// for `event_reader`, `write_to` encodes `data` again either way.
+    pub fn calc_data(&mut self) -> Result<()> {
+        if let Some(compressed_data) = &self.compressed_data {
+            self.data = Some(ReplayData::from_reader(&mut &compressed_data[..])?);
+            Ok(())
+        } else {
+            bail!("No compressed data");
+        }
+    }
+
+    pub fn entity_lifecycles(&mut self) -> Result<Vec<EntityLifecycle>> {
+        if self.data.is_none() {
+            self.calc_data()?;
+        }
+
+        Ok(self.data.as_ref().unwrap().entity_lifecycles())
+    }
+
+    /// Lazily decodes the events from the compressed payload without
+    /// building the whole `ReplayData`. This works before and after
+    /// `calc_data`, but it always streams `compressed_data`, edits made to
+    /// `data` only show up after `compress_data`.
+    pub fn event_reader(&self) -> Result<ReplayEventReader<&[u8]>> {
+        match &self.compressed_data {
+            Some(compressed_data) => Ok(ReplayEventReader::new(&compressed_data[..])?),
+            None => bail!("No compressed data"),
+        }
+    }
+
+    pub fn compress_data(&mut self) -> Result<()> {
+        if let Some(data) = &self.data {
+            let mut compressed_data = vec![];
+            data.write_to(&mut compressed_data)?;
+            self.header.compressed_data_len = compressed_data.len() as u32;
+            self.compressed_data = Some(compressed_data);
+            Ok(())
+        } else {
+            bail!("No replay data");
+        }
+    }
+
+    /// The spawnset the replay was recorded on, decoded on first use
+    pub fn spawnset(&mut self) -> Result<&AnySpawnset> {
+        if self.header.spawnset.is_none() {
+            self.header.create_spawnset()?;
+        }
+
+        Ok(self.header.spawnset.as_ref().unwrap())
+    }
+
+    /// Hand progression of the run, using the settings of the embedded spawnset
+    pub fn progression(&mut self) -> Result<Progression> {
+        if self.data.is_none() {
+            self.calc_data()?;
+        }
+
+        let settings = self.spawnset()?.settings().cloned();
+        Ok(self.data.as_ref().unwrap().progression(settings.as_ref()))
+    }
+
+    /// Fills `extra` from `DdRpl::progression`
+    pub fn create_extra(&mut self) -> Result<()> {
+        let progression = self.progression()?;
+        let look_speed = self.data.as_ref().unwrap().look_speed().unwrap_or(0.);
+
+        self.extra = Some(ExtraData {
+            homing: progression.frames.iter().map(|frame| frame.homing).collect(),
+            homing_used: progression.frames.iter().map(|frame| frame.homing_used).collect(),
+            starting_gems: progression.additional_gems,
+            starting_hand: progression.initial_hand,
+            look_speed,
+            lvl2_time: progression.level_up_time(2).unwrap_or(0.),
+            lvl3_time: progression.level_up_time(3).unwrap_or(0.),
+            lvl4_time: progression.level_up_time(4).unwrap_or(0.),
+        });
+
+        Ok(())
+    }
+
+    /// Checks the header without decompressing the events. A spawnset that
+    /// doesn't match the header's md5 hash is rejected with `SpawnsetHashMismatch`.
+    pub fn validate_reader<R: Read + Seek>(source: &mut R) -> Result<(), ReplayError> {
+        DdRplHeader::read_from(source)?;
+        Ok(())
+    }
+
+    /// Same checks as `DdRpl::validate_reader`, returns the whole file when they pass
+    pub fn validate_reader_output_bin<R: Read + Seek>(source: &mut R) -> Result<Vec<u8>, ReplayError> {
+        DdRplHeader::read_from(source)?;
+        source.seek(SeekFrom::Start(0))?;
+
+        let mut out = vec![];
+        source.read_to_end(&mut out)?;
+        Ok(out)
+    }
+
+    pub fn from_reader<R: Read>(source: &mut R) -> Result<Self, ReplayError> {
+        let mut r = OffsetReader::new(source);
+        let header = DdRplHeader::read_fields(&mut r)?;
+
+        let compressed_data = Some(r.read_bytes(header.compressed_data_len as u64)?);
+
+        let offset = r.offset;
+        if r.into_inner().read(&mut [0u8; 1])? != 0 {
+            return Err(ReplayError::TrailingData { offset });
+        }
+
+        Ok(DdRpl {
+            header,
+            data: None,
+            compressed_data,
+            extra: None,
+        })
+    }
+
+    /// Writes the replay as a `.ddreplay` file. The events are encoded again
+    /// from `data` whenever it's there so edits to it are kept, the original
+    /// `compressed_data` is only written back while the events were never decoded.
+    pub fn write_to<W: Write>(&self, sink: &mut W) -> Result<()> {
+        use bytestream::*;
+
+        let encoded;
+        let compressed_data = match (&self.data, &self.compressed_data) {
+            (Some(data), _) => {
+                encoded = {
+                    let mut buf = vec![];
+                    data.write_to(&mut buf)?;
+                    buf
+                };
+                &encoded
+            },
+            (None, Some(compressed_data)) => compressed_data,
+            (None, None) => bail!("No replay data"),
+        };
+
+        let header = &self.header;
+        let timestamp = match header.recorded_at.duration_since(UNIX_EPOCH)?.as_secs().checked_sub(DD_RELEASE_TIMESTAMP) {
+            Some(timestamp) => timestamp,
+            None => bail!("Replay timestamp is older than the game"),
+        };
+        let spawnset_hash = crate::utils::decode_hex(&header.spawnset_hash)?;
+        if spawnset_hash.len() != 16 {
+            bail!("Invalid spawnset hash");
+        }
+
+        sink.write_all(b"ddrpl.")?;
+        header.file_version.write_to(sink, ByteOrder::LittleEndian)?;
+        timestamp.write_to(sink, ByteOrder::LittleEndian)?;
+        sink.write_all(&header.time.to_le_bytes())?;
+        sink.write_all(&header.starting_time.to_le_bytes())?;
+        header.daggers_fired.write_to(sink, ByteOrder::LittleEndian)?;
+        header.death_type.write_to(sink, ByteOrder::LittleEndian)?;
+        header.gems.write_to(sink, ByteOrder::LittleEndian)?;
+        header.daggers_hit.write_to(sink, ByteOrder::LittleEndian)?;
+        header.kills.write_to(sink, ByteOrder::LittleEndian)?;
+        header.player_id.write_to(sink, ByteOrder::LittleEndian)?;
+        (header.player_name.len() as u32).write_to(sink, ByteOrder::LittleEndian)?;
+        sink.write_all(header.player_name.as_bytes())?;
+        sink.write_all(&header.unknown)?;
+        sink.write_all(&spawnset_hash)?;
+        (header.spawnset_bin.len() as u32).write_to(sink, ByteOrder::LittleEndian)?;
+        sink.write_all(&header.spawnset_bin)?;
+        (compressed_data.len() as u32).write_to(sink, ByteOrder::LittleEndian)?;
+        sink.write_all(compressed_data)?;
+        sink.flush()?;
+        Ok(())
+    }
+}
+
+impl DfRpl2 {
+    pub fn new(header: DfRpl2Header, data: ReplayData) -> Self {
+        Self { header, data, compressed_data: None }
+    }
+
+    pub fn data(&self) -> &ReplayData {
+        &self.data
+    }
+
+    /// Drops the payload that was read, `write_to` encodes the events from now on
+    pub fn data_mut(&mut self) -> &mut ReplayData {
+        self.compressed_data = None;
+        &mut self.data
+    }
+
+    pub fn from_reader<R: Read>(source: &mut R) -> Result<Self, ReplayError> {
+        let mut r = OffsetReader::new(source);
+
+        let magic = r.read_bytes(7)?;
+        if magic != b"DF_RPL2" {
+            return Err(ReplayError::InvalidMagic { found: magic });
+        }
+        let username_len = r.read_u16()?;
+        let offset = r.offset;
+        let username = String::from_utf8(r.read_bytes(username_len as u64)?)
+            .map_err(|_| ReplayError::InvalidUsername { offset })?;
+        let funny_bytes_len = r.read_u16()?;
+        let funny_bytes = r.read_bytes(funny_bytes_len as u64)?;
+
+        let header = DfRpl2Header {
+            player_name: username,
+            funny_bytes
+        };
+
+        let mut compressed_data = vec![];
+        r.into_inner().read_to_end(&mut compressed_data)?;
+        let data = ReplayData::from_reader(&mut &compressed_data[..])?;
+
+        Ok(DfRpl2 {
+            header,
+            data,
+            compressed_data: Some(compressed_data),
+        })
+    }
+
+    pub fn write_to<W: Write>(&self, sink: &mut W) -> Result<()> {
+        use bytestream::*;
+
+        let (name, funny_bytes) = (self.header.player_name.as_bytes(), &self.header.funny_bytes);
+        if name.len() > u16::MAX as usize || funny_bytes.len() > u16::MAX as usize {
+            bail!("DF_RPL2 header field too long");
+        }
+
+        sink.write_all(b"DF_RPL2")?;
+        (name.len() as u16).write_to(sink, ByteOrder::LittleEndian)?;
+        sink.write_all(name)?;
+        (funny_bytes.len() as u16).write_to(sink, ByteOrder::LittleEndian)?;
+        sink.write_all(funny_bytes)?;
+        match &self.compressed_data {
+            Some(compressed_data) => sink.write_all(compressed_data)?,
+            None => self.data.write_to(sink)?,
+        }
+        sink.flush()?;
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub(crate) fn end_frame(look_speed: Option<f32>) -> ReplayEvent {
+        ReplayEvent::EndFrame(
+            ButtonData {
+                left: KeyState::Held,
+                right: KeyState::NotPressed,
+                forward: KeyState::Held,
+                backwards: KeyState::NotPressed,
+                jump: JumpButtonState::JustPressed,
+                shoot: MouseButtonState::Held,
+                homing: MouseButtonState::Released,
+            },
+            MouseData { x: -12, y: 7, look_speed },
+        )
+    }
+
+    pub(crate) fn test_data() -> ReplayData {
+        let frames = vec![
+            ReplayFrame {
+                events: vec![
+                    ReplayEvent::Spawn(EntityData::Squid1(SquidData {
+                        a: 0,
+                        position: [10., 0., -10.],
+                        b: [0., 1., 0.],
+                        rotation: 1.5,
+                    })),
+                    ReplayEvent::Spawn(EntityData::Dagger(DaggerData {
+                        a: 0,
+                        position: [1, 2, 3],
+                        orientationa: [16384, 0, 0],
+                        orientationb: [0, 16384, 0],
+                        orientationc: [0, 0, 16384],
+                        b: 0,
+                        dagger_level: DaggerLevel::Level3,
+                    })),
+                    ReplayEvent::Spawn(EntityData::Boid(BoidData {
+                        boid_type: BoidType::Skull2,
+                        spanwer: 1,
+                        position: [100, 200, 300],
+                        funny1: [1, 2, 3],
+                        funny2: [4, 5, 6],
+                        funny3: [7, 8, 9],
+                        funny4: [0.5, 0.25, 0.125],
+                        speed: 4.,
+                    })),
+                    end_frame(Some(2.3)),
+                ],
+            },
+            ReplayFrame {
+                events: vec![
+                    ReplayEvent::UpdateEntityPosition(1, [4, 5, 6]),
+                    ReplayEvent::UpdateEntityOrientation(1, UpdateOrientationData { a: [1, 2, 3], b: [4, 5, 6], c: [7, 8, 9] }),
+                    ReplayEvent::UpdateEntityTarget(1, [-1, -2, -3]),
+                    ReplayEvent::EnemyHitWeakSpot(EnemyHitData { enemy_id: 1, dagger_id: 2, segment: 0 }),
+                    ReplayEvent::EnemyHitArmor(EnemyHitData { enemy_id: 1, dagger_id: 2, segment: 3 }),
+                    ReplayEvent::DaggerDewspawn(DaggerDespawnData { dagger_id: 2 }),
+                    ReplayEvent::GemPickup,
+                    ReplayEvent::Transmute(1, TransmuteData { a: [1, 1, 1], b: [2, 2, 2], c: [3, 3, 3], d: [4, 4, 4] }),
+                    end_frame(None),
+                ],
+            },
+            ReplayFrame {
+                events: vec![
+                    ReplayEvent::PlayerDeath(PlayerDeathData { death_type: 3, unknown: 0 }),
+                    ReplayEvent::EndReplay,
+                ],
+            },
+        ];
+
+        ReplayData { frames, entities: vec![], trailing_bytes: vec![] }
+    }
+
+    /// The first two frames of `test_data` followed by frames up to `frames`,
+    /// each one with the events `events` returns for it and an `EndFrame`
+    pub(crate) fn extend_test_data(frames: usize, mut events: impl FnMut(usize) -> Vec<ReplayEvent>) -> ReplayData {
+        let mut data = test_data();
+        data.frames.truncate(2);
+        for frame in 2..frames {
+            let mut frame_events = events(frame);
+            frame_events.push(end_frame(None));
+            data.frames.push(ReplayFrame { events: frame_events });
+        }
+        data
+    }
+
+    /// A skull spawned by the squid from `test_data`
+    pub(crate) fn skull_spawn(boid_type: BoidType) -> ReplayEvent {
+        ReplayEvent::Spawn(EntityData::Boid(BoidData {
+            boid_type,
+            spanwer: 1,
+            position: [0; 3],
+            funny1: [0; 3],
+            funny2: [0; 3],
+            funny3: [0; 3],
+            funny4: [0.; 3],
+            speed: 1.,
+        }))
+    }
+
+    fn compress(events: &[u8]) -> Vec<u8> {
+        let mut encoder = libflate::zlib::Encoder::new(Vec::new()).unwrap();
+        encoder.write_all(events).unwrap();
+        encoder.finish().into_result().unwrap()
+    }
+
+    pub(crate) fn test_replay() -> DdRpl {
+        let compressed_data = compress(&test_data().encode_events().unwrap());
+        DdRpl {
+            header: DdRplHeader {
+                file_version: 1,
+                recorded_at: UNIX_EPOCH + Duration::from_secs(DD_RELEASE_TIMESTAMP + 200_000_000),
+                time: 1. / 60.,
+                starting_time: 0.,
+                daggers_fired: 0,
+                death_type: 0,
+                gems: 0,
+                kills: 0,
+                daggers_hit: 0,
+                player_name: "xvlv".into(),
+                player_id: 21854,
+                unknown: [1, 2, 3, 4, 5, 6, 7, 8, 9, 10],
+                spawnset_bin: vec![0xAB; 32],
+                spawnset_hash: spawnset_md5(&[0xAB; 32]),
+                compressed_data_len: compressed_data.len() as u32,
+                spawnset: None,
+            },
+            compressed_data: Some(compressed_data),
+            data: None,
+            extra: None,
+        }
+    }
+
+    #[test]
+    fn ddrpl_write_round_trip() {
+        let mut first = vec![];
+        test_replay().write_to(&mut first).unwrap();
+
+        let mut parsed = DdRpl::from_reader(&mut &first[..]).unwrap();
+        assert_eq!(parsed.header.player_name, "xvlv");
+        assert_eq!(parsed.header.player_id, 21854);
+        assert_eq!(parsed.header.unknown, [1, 2, 3, 4, 5, 6, 7, 8, 9, 10]);
+        assert!(parsed.header.verify_spawnset_hash());
+
+        let mut second = vec![];
+        parsed.write_to(&mut second).unwrap();
+        assert_eq!(first, second);
+
+        parsed.calc_data().unwrap();
+        assert_eq!(parsed.data.unwrap().frames.len(), 3);
+    }
+
+    #[test]
+    fn event_reader_after_calc_data() {
+        let mut replay = DdRpl::from_reader(&mut &test_replay_file()[..]).unwrap();
+        let streamed = replay.event_reader().unwrap().collect::<Result<Vec<_>, _>>().unwrap();
+        replay.calc_data().unwrap();
+        assert_eq!(replay.event_reader().unwrap().collect::<Result<Vec<_>, _>>().unwrap(), streamed);
+
+        let decoded = replay.data.unwrap().frames.into_iter()
+            .enumerate()
+            .flat_map(|(frame, replay_frame)| replay_frame.events.into_iter().map(move |event| (frame, event)))
+            .collect::<Vec<_>>();
+        assert_eq!(streamed, decoded);
+    }
+
+    #[test]
+    fn ddrpl_writes_edited_events() {
+        let mut replay = DdRpl::from_reader(&mut &test_replay_file()[..]).unwrap();
+        let mut data = ReplayData::from_reader(&mut &replay.compressed_data.as_ref().unwrap()[..]).unwrap();
+        data.frames[1].events.retain(|event| *event != ReplayEvent::GemPickup);
+        replay.data = Some(data);
+
+        let mut written = vec![];
+        replay.write_to(&mut written).unwrap();
+        let mut parsed = DdRpl::from_reader(&mut &written[..]).unwrap();
+        parsed.calc_data().unwrap();
+        assert_eq!(parsed.data.unwrap().frames, replay.data.unwrap().frames);
+    }
+
+    #[test]
+    fn dfrpl2_keeps_the_original_payload() {
+        // Stored blocks, the encoder would compress these differently
+        let options = libflate::zlib::EncodeOptions::new().no_compression();
+        let mut encoder = libflate::zlib::Encoder::with_options(Vec::new(), options).unwrap();
+        encoder.write_all(&test_data().encode_events().unwrap()).unwrap();
+        let mut file = b"DF_RPL2\x04\x00xvlv\x02\x00\x01\x02".to_vec();
+        file.extend(encoder.finish().into_result().unwrap());
+
+        let mut parsed = DfRpl2::from_reader(&mut &file[..]).unwrap();
+        assert_eq!(parsed.data().frames, test_data().frames);
+        let mut written = vec![];
+        parsed.write_to(&mut written).unwrap();
+        assert_eq!(written, file);
+
+        parsed.data_mut().frames[1].events.retain(|event| *event != ReplayEvent::GemPickup);
+        written.clear();
+        parsed.write_to(&mut written).unwrap();
+        assert_ne!(written, file);
+        assert_eq!(DfRpl2::from_reader(&mut &written[..]).unwrap().data().frames, parsed.data().frames);
+    }
+
+    fn test_replay_file() -> Vec<u8> {
+        let mut file = vec![];
+        test_replay().write_to(&mut file).unwrap();
+        file
+    }
+
+    // Small deterministic generator so the fuzz style tests are reproducible
+    fn lcg(state: &mut u64) -> u64 {
+        *state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        *state >> 33
+    }
+
+    #[test]
+    fn truncated_replays_fail() {
+        let file = test_replay_file();
+        for len in 0..file.len() {
+            assert!(DdRpl::from_reader(&mut &file[..len]).is_err(), "accepted replay truncated to {} bytes", len);
+        }
+
+        let events = test_data().encode_events().unwrap();
+        for len in 0..events.len() {
+            assert!(ReplayData::from_reader(&mut &compress(&events[..len])[..]).is_err());
+        }
+
+        let compressed = compress(&events);
+        for len in 0..compressed.len() {
+            assert!(ReplayData::from_reader(&mut &compressed[..len]).is_err());
+        }
+    }
+
+    #[test]
+    fn malformed_replays_dont_panic() {
+        let mut state = 0xDD;
+        let file = test_replay_file();
+        let events = test_data().encode_events().unwrap();
+        for _ in 0..2000 {
+            let mut file = file.clone();
+            let mut events = events.clone();
+            for _ in 0..1 + lcg(&mut state) % 4 {
+                let i = lcg(&mut state) as usize % file.len();
+                file[i] = lcg(&mut state) as u8;
+                let i = lcg(&mut state) as usize % events.len();
+                events[i] = lcg(&mut state) as u8;
+            }
+            let _ = DdRpl::from_reader(&mut &file[..]);
+            let _ = DfRpl2::from_reader(&mut &file[..]);
+            let _ = ReplayData::from_reader(&mut &compress(&events)[..]);
+        }
+    }
+
+    #[test]
+    fn malformed_header_fields() {
+        let file = test_replay_file();
+        let username_len_offset = 0x32;
+
+        let mut bad_magic = file.clone();
+        bad_magic[0] = b'x';
+        assert!(matches!(DdRpl::from_reader(&mut &bad_magic[..]), Err(ReplayError::InvalidMagic { .. })));
+
+        let mut bad_timestamp = file.clone();
+        bad_timestamp[0xA..0x12].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(matches!(DdRpl::from_reader(&mut &bad_timestamp[..]), Err(ReplayError::InvalidTimestamp { offset: 0xA, .. })));
+
+        let mut huge_username = file.clone();
+        huge_username[username_len_offset..username_len_offset + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(DdRpl::from_reader(&mut &huge_username[..]), Err(ReplayError::UnexpectedEof { offset: 0x36, .. })));
+
+        let mut trailing = file.clone();
+        trailing.push(0);
+        assert!(matches!(DdRpl::from_reader(&mut &trailing[..]), Err(ReplayError::TrailingData { .. })));
+    }
+
+    #[test]
+    fn invalid_values_report_context() {
+        let events = test_data().encode_events().unwrap();
+        let parse = |events: &[u8]| ReplayData::from_reader(&mut &compress(events)[..]);
+
+        let mut bad_entity = events.clone();
+        bad_entity[1] = 0xE;
+        assert!(matches!(parse(&bad_entity), Err(ReplayError::InvalidEntityType { offset: 1, frame: 0, value: 0xE })));
+
+        // squid spawn is 2 + 4 + 28 bytes, the dagger level is the last byte of the dagger spawn
+        let dagger_level = 34 + 2 + 4 + 24 + 1;
+        let mut bad_dagger = events.clone();
+        bad_dagger[dagger_level] = 9;
+        assert!(matches!(
+            parse(&bad_dagger),
+            Err(ReplayError::InvalidDaggerLevel { frame: 0, value: 9, .. })
+        ));
+
+        let boid_type = dagger_level + 1 + 2 + 4;
+        let mut bad_boid = events.clone();
+        bad_boid[boid_type] = 0;
+        assert!(matches!(
+            parse(&bad_boid),
+            Err(ReplayError::InvalidBoidType { frame: 0, value: 0, .. })
+        ));
+
+        let mut bad_terminator = events.clone();
+        let terminator = events.iter().position(|b| *b == 0xA).unwrap();
+        bad_terminator[terminator] = 0xC;
+        assert!(parse(&bad_terminator).is_err());
+
+        let mut truncated = events;
+        truncated.truncate(truncated.len() - 1);
+        assert!(matches!(
+            parse(&truncated),
+            Err(ReplayError::UnexpectedEof { frame: Some(2), event_type: None, .. })
+        ));
+    }
+
+    #[test]
+    fn header_only_read() {
+        let file = test_replay_file();
+        let mut cursor = std::io::Cursor::new(&file);
+        let header = DdRplHeader::read_from(&mut cursor).unwrap();
+        assert_eq!(cursor.position(), file.len() as u64);
+        assert_eq!(header.player_name, "xvlv");
+        assert_eq!(header.compressed_data_len, test_replay().header.compressed_data_len);
+        assert_eq!(DdRpl::validate_reader_output_bin(&mut std::io::Cursor::new(&file)).unwrap(), file);
+
+        let truncated = &file[..file.len() - 1];
+        assert!(matches!(
+            DdRpl::validate_reader(&mut std::io::Cursor::new(truncated)),
+            Err(ReplayError::UnexpectedEof { .. })
+        ));
+
+        let mut trailing = file.clone();
+        trailing.push(0);
+        assert!(matches!(
+            DdRpl::validate_reader(&mut std::io::Cursor::new(&trailing)),
+            Err(ReplayError::TrailingData { offset }) if offset == file.len() as u64
+        ));
+    }
+
+    #[test]
+    fn embedded_spawnset_is_checked_and_decoded() {
+        use crate::models::spawnset::{Header, Settings, Spawn, Spawnset, V1Enemies, V3Enemies};
+
+        let mut file = vec![];
+        test_replay().write_to(&mut file).unwrap();
+        // First byte of the spawnset, after the 16 byte hash and its u32 length
+        let spawnset_offset = 0x36 + 4 + 10 + 16 + 4;
+        file[spawnset_offset] ^= 1;
+        assert!(matches!(
+            DdRpl::from_reader(&mut &file[..]),
+            Err(ReplayError::SpawnsetHashMismatch { offset: 0x44, .. })
+        ));
+        assert!(matches!(
+            DdRpl::validate_reader(&mut std::io::Cursor::new(&file)),
+            Err(ReplayError::SpawnsetHashMismatch { offset: 0x44, .. })
+        ));
+        assert!(matches!(
+            DdRpl::validate_reader_output_bin(&mut std::io::Cursor::new(&file)),
+            Err(ReplayError::SpawnsetHashMismatch { .. })
+        ));
+
+        let mut spawnset = Spawnset::<V3Enemies> {
+            header: Header::default(),
+            arena: Default::default(),
+            spawns_header: Default::default(),
+            spawns: vec![Spawn { enemy_type: V3Enemies::Thorn, ..Default::default() }],
+            settings: Some(Settings { initial_hand: 3, additional_gems: 5, timer_start: Some(0.) }),
+        };
+        spawnset.recalculate_spawn_count();
+        let mut replay = test_replay();
+        replay.header.spawnset_bin.clear();
+        spawnset.serialize(&mut replay.header.spawnset_bin).unwrap();
+        replay.header.spawnset_hash = spawnset_md5(&replay.header.spawnset_bin);
+        let mut file = vec![];
+        replay.write_to(&mut file).unwrap();
+
+        let mut parsed = DdRpl::from_reader(&mut &file[..]).unwrap();
+        let AnySpawnset::V3(decoded) = parsed.spawnset().unwrap() else { panic!() };
+        assert_eq!(decoded.spawns[0].enemy_type, V3Enemies::Thorn);
+        assert_eq!(parsed.progression().unwrap().frames[0].homing, 5);
+
+        let v1 = Spawnset::<V1Enemies> {
+            header: Header { spawn_version: 4, world_version: 8, ..Default::default() },
+            arena: Default::default(),
+            spawns_header: Default::default(),
+            spawns: vec![Spawn { enemy_type: V1Enemies::Gigapede, ..Default::default() }],
+            settings: None,
+        };
+        replay.header.spawnset_bin.clear();
+        v1.serialize(&mut replay.header.spawnset_bin).unwrap();
+        replay.header.create_spawnset().unwrap();
+        assert!(matches!(replay.header.spawnset, Some(AnySpawnset::V1(_))));
+    }
+}
@@ -0,0 +1,245 @@
+//
+// hand level and homing progression
+//
+
+use crate::models::spawnset::Settings;
+use super::*;
+
+/// Hand, gem and homing counters at the end of one replay frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProgressionFrame {
+    pub hand_level: u8,
+    pub gems: i32,
+    pub level_gems: i32,
+    pub homing: i32,
+    pub homing_used: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LevelUp {
+    pub level: u8,
+    pub frame: usize,
+    pub time: f32, // In game timer, includes the spawnset's timer start
+}
+
+/// Hand progression over a whole replay, one `ProgressionFrame` per replay frame.
+///
+/// Homing counts up from the gems collected once the hand reaches level 3 and
+/// goes down by one for every level 6 dagger, level 4 is reached after
+/// collecting 150 gems at level 3, which resets the homing count.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Progression {
+    pub initial_hand: u8,
+    pub additional_gems: i32,
+    pub timer_start: f32,
+    pub frames: Vec<ProgressionFrame>,
+    pub level_ups: Vec<LevelUp>,
+}
+
+/// Gem and homing bookkeeping shared by everything that needs the hand level
+#[derive(Debug, Clone)]
+pub(crate) struct HandState {
+    pub hand_level: u8,
+    pub level_gems: i32,
+    pub homing: i32,
+    pub homing_used: i32,
+    pub gems: i32,
+}
+
+impl HandState {
+    /// The additional gems count toward the starting hand, so they can level
+    /// it up before the first frame
+    pub fn new(initial_hand: u8, additional_gems: i32) -> Self {
+        let hand_level = initial_hand.clamp(1, 4);
+        let level_gems = [0, 10, 70, 71][hand_level as usize - 1];
+        let mut hand = Self { hand_level, level_gems, homing: 0, homing_used: 0, gems: additional_gems };
+        hand.add_gems(additional_gems);
+        hand
+    }
+
+    /// Returns the new hand level if the gem levelled the hand up
+    pub fn gem_pickup(&mut self) -> Option<u8> {
+        self.gems += 1;
+        self.add_gems(1)
+    }
+
+    // Gems past a level up threshold count toward the next level
+    fn add_gems(&mut self, gems: i32) -> Option<u8> {
+        let start = self.hand_level;
+        match self.hand_level {
+            1 | 2 => self.level_gems += gems,
+            _ => self.homing += gems,
+        }
+        if self.hand_level == 1 && self.level_gems >= 10 {
+            self.hand_level = 2;
+        }
+        if self.hand_level == 2 && self.level_gems >= 70 {
+            self.hand_level = 3;
+            self.homing += self.level_gems - 70;
+            self.level_gems = 70;
+        }
+        if self.hand_level == 3 && self.homing >= 150 {
+            self.hand_level = 4;
+            self.level_gems = 71;
+            self.homing -= 150;
+        }
+        (self.hand_level != start).then_some(self.hand_level)
+    }
+
+    pub fn dagger_spawn(&mut self, dagger: &DaggerData) {
+        if dagger.dagger_level == DaggerLevel::Level6 {
+            self.homing -= 1;
+            self.homing_used += 1;
+        }
+    }
+
+    pub fn apply(&mut self, event: &ReplayEvent) -> Option<u8> {
+        match event {
+            ReplayEvent::GemPickup => self.gem_pickup(),
+            ReplayEvent::Spawn(EntityData::Dagger(dagger)) => {
+                self.dagger_spawn(dagger);
+                None
+            },
+            _ => None,
+        }
+    }
+}
+
+impl HandState {
+    pub fn frame(&self) -> ProgressionFrame {
+        ProgressionFrame {
+            hand_level: self.hand_level,
+            gems: self.gems,
+            level_gems: self.level_gems,
+            homing: self.homing,
+            homing_used: self.homing_used,
+        }
+    }
+}
+
+impl Progression {
+    /// Counters at the end of `frame`, `None` past the end of the replay
+    pub fn at_frame(&self, frame: usize) -> Option<&ProgressionFrame> {
+        self.frames.get(frame)
+    }
+
+    /// Counters at the given in game time, clamped to the replay
+    pub fn at_time(&self, time: f32) -> Option<&ProgressionFrame> {
+        let last = self.frames.len().checked_sub(1)?;
+        let frame = ((time - self.timer_start) * FRAMES_PER_SECOND as f32).round() as i64 - 1;
+        self.frames.get(frame.clamp(0, last as i64) as usize)
+    }
+
+    pub fn level_up_time(&self, level: u8) -> Option<f32> {
+        self.level_ups.iter().find(|level_up| level_up.level == level).map(|level_up| level_up.time)
+    }
+
+    pub fn max_homing(&self) -> i32 {
+        self.frames.iter().map(|frame| frame.homing).max().unwrap_or(0)
+    }
+}
+
+impl ReplayData {
+    /// Progression of the hand through the replay, without settings the run
+    /// is assumed to start with a level 1 hand, no extra gems and the timer at 0
+    pub fn progression(&self, settings: Option<&Settings>) -> Progression {
+        let initial_hand = settings.map_or(1, |s| s.initial_hand);
+        let additional_gems = settings.map_or(0, |s| s.additional_gems);
+        let timer_start = settings.and_then(|s| s.timer_start).unwrap_or(0.);
+
+        let mut hand = HandState::new(initial_hand, additional_gems);
+        let mut frames = Vec::with_capacity(self.frames.len());
+        let mut level_ups = vec![];
+        for (frame, replay_frame) in self.frames.iter().enumerate() {
+            for event in &replay_frame.events {
+                if let Some(level) = hand.apply(event) {
+                    level_ups.push(LevelUp { level, frame, time: timer_start + (frame + 1) as f32 / FRAMES_PER_SECOND as f32 });
+                }
+            }
+            frames.push(hand.frame());
+        }
+
+        Progression { initial_hand: initial_hand.clamp(1, 4), additional_gems, timer_start, frames, level_ups }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::replay::tests::{end_frame, test_data};
+
+    fn gem_frames(data: &mut ReplayData, gems: usize, homing_daggers: usize) {
+        for i in 0..gems.max(homing_daggers) {
+            let mut events = vec![];
+            if i < gems {
+                events.push(ReplayEvent::GemPickup);
+            }
+            if i < homing_daggers {
+                events.push(ReplayEvent::Spawn(EntityData::Dagger(DaggerData {
+                    a: 0,
+                    position: [0; 3],
+                    orientationa: [0; 3],
+                    orientationb: [0; 3],
+                    orientationc: [0; 3],
+                    b: 0,
+                    dagger_level: DaggerLevel::Level6,
+                })));
+            }
+            events.push(end_frame(None));
+            data.frames.insert(data.frames.len() - 1, ReplayFrame { events });
+        }
+    }
+
+    #[test]
+    fn levels_up_at_the_right_gem_counts() {
+        let mut data = test_data();
+        gem_frames(&mut data, 250, 0);
+        let progression = data.progression(None);
+        assert_eq!(progression.frames.len(), data.frames.len());
+        let levels = progression.level_ups.iter().map(|l| (l.level, l.frame)).collect::<Vec<_>>();
+        // test_data picks up its only gem in frame 1, the extra frames start at 2
+        assert_eq!(levels, vec![(2, 10), (3, 70), (4, 220)]);
+        assert_eq!(progression.level_up_time(2), Some(11. / 60.));
+        assert_eq!(progression.at_frame(219).unwrap().homing, 149);
+        assert_eq!(progression.at_frame(220).unwrap().homing, 0);
+        let last = progression.frames.last().unwrap();
+        assert_eq!((last.hand_level, last.gems, last.level_gems, last.homing), (4, 251, 71, 31));
+    }
+
+    #[test]
+    fn respects_spawnset_settings() {
+        let mut data = test_data();
+        gem_frames(&mut data, 5, 8);
+        let settings = Settings { initial_hand: 3, additional_gems: 20, timer_start: Some(30.) };
+        let progression = data.progression(Some(&settings));
+        assert!(progression.level_ups.is_empty());
+        let last = progression.frames.last().unwrap();
+        assert_eq!((last.hand_level, last.homing, last.homing_used), (3, 20 + 6 - 8, 8));
+        assert_eq!(progression.at_time(30. + 3. / 60.), progression.at_frame(2));
+
+        // The extra gems level the hand up before the first frame, the rest carries over
+        let settings = Settings { initial_hand: 1, additional_gems: 65, timer_start: None };
+        let progression = data.progression(Some(&settings));
+        let first = progression.frames[0];
+        assert_eq!((first.hand_level, first.level_gems, first.homing), (2, 65, 0));
+        let levels = progression.level_ups.iter().map(|l| (l.level, l.frame)).collect::<Vec<_>>();
+        assert_eq!(levels, vec![(3, 5)]);
+
+        let settings = Settings { initial_hand: 2, additional_gems: 65, timer_start: None };
+        let progression = data.progression(Some(&settings));
+        let first = progression.frames[0];
+        assert_eq!((first.hand_level, first.level_gems, first.homing), (3, 70, 5));
+        assert!(progression.level_ups.is_empty());
+
+        let hand = HandState::new(1, 300);
+        assert_eq!((hand.hand_level, hand.level_gems, hand.homing, hand.gems), (4, 71, 80, 300));
+    }
+
+    #[test]
+    fn empty_replays_have_no_frames() {
+        let data = ReplayData { frames: vec![], entities: vec![], trailing_bytes: vec![] };
+        let progression = data.progression(None);
+        assert_eq!(progression.at_time(0.), None);
+        assert_eq!(progression.at_time(10.), None);
+    }
+}
@@ -0,0 +1,265 @@
+//
+// world state reconstruction
+//
+
+use std::collections::BTreeMap;
+
+use super::lifecycle::{count_weak_spot_hit, LifecycleEvent};
+use super::*;
+
+// Frames between two checkpoints, 10 seconds of game time
+const CHECKPOINT_INTERVAL: usize = 600;
+
+/// One entity that is alive at the current frame of a `ReplayState`
+#[derive(Debug, Clone, PartialEq)]
+pub struct EntityState {
+    pub id: EntityId,
+    pub entity_type: EntityType,
+    pub enemy_type: Option<EnemyType>,
+    pub position: Option<EntityPosition>,
+    pub orientation: Option<UpdateOrientationData>,
+    pub target: Option<PositionInt>,
+    pub dagger_level: Option<DaggerLevel>,
+    pub transmuted: bool,
+    pub weak_spot_hits: u32,
+}
+
+#[derive(Debug, Clone)]
+struct Snapshot {
+    next_id: EntityId,
+    entities: BTreeMap<EntityId, EntityState>,
+}
+
+/// Replays the events of a `ReplayData` to rebuild the world frame by frame.
+///
+/// The state is always the one at the start of `frame()`, so a fresh state
+/// is empty and `step` applies the events of one frame. Entities are removed
+/// when they die following the same rules as `LifecycleTracker`. `seek`
+/// restores the closest checkpoint recorded along the way, checkpoints are
+/// taken every 600 frames as the state moves forward.
+#[derive(Debug, Clone)]
+pub struct ReplayState<'a> {
+    data: &'a ReplayData,
+    frame: usize,
+    current: Snapshot,
+    checkpoints: Vec<Snapshot>,
+}
+
+impl<'a> ReplayState<'a> {
+    pub fn new(data: &'a ReplayData) -> Self {
+        let current = Snapshot { next_id: 1, entities: BTreeMap::new() };
+        Self { data, frame: 0, checkpoints: vec![current.clone()], current }
+    }
+
+    pub fn frame(&self) -> usize {
+        self.frame
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.data.frames.len()
+    }
+
+    pub fn entity(&self, id: EntityId) -> Option<&EntityState> {
+        self.current.entities.get(&id)
+    }
+
+    /// Live entities ordered by id
+    pub fn entities(&self) -> impl Iterator<Item = &EntityState> {
+        self.current.entities.values()
+    }
+
+    pub fn enemies(&self) -> impl Iterator<Item = &EntityState> {
+        self.entities().filter(|entity| entity.enemy_type.is_some())
+    }
+
+    pub fn daggers(&self) -> impl Iterator<Item = &EntityState> {
+        self.entities().filter(|entity| entity.entity_type == EntityType::Dagger)
+    }
+
+    /// Applies the events of the current frame, returns false once the end
+    /// of the replay has been reached
+    pub fn step(&mut self) -> bool {
+        let Some(replay_frame) = self.data.frames.get(self.frame) else {
+            return false;
+        };
+        for event in &replay_frame.events {
+            self.current.apply(event);
+        }
+        self.frame += 1;
+        if self.frame == self.checkpoints.len() * CHECKPOINT_INTERVAL {
+            self.checkpoints.push(self.current.clone());
+        }
+        true
+    }
+
+    /// Moves to the start of `frame`, clamped to the end of the replay
+    pub fn seek(&mut self, frame: usize) {
+        let frame = frame.min(self.frame_count());
+        let checkpoint = (frame / CHECKPOINT_INTERVAL).min(self.checkpoints.len() - 1);
+        let checkpoint_frame = checkpoint * CHECKPOINT_INTERVAL;
+        if frame < self.frame || checkpoint_frame > self.frame {
+            self.current = self.checkpoints[checkpoint].clone();
+            self.frame = checkpoint_frame;
+        }
+        while self.frame < frame {
+            self.step();
+        }
+    }
+}
+
+impl Snapshot {
+    fn apply(&mut self, event: &ReplayEvent) {
+        match LifecycleEvent::from_event(event) {
+            Some(LifecycleEvent::Spawn(entity)) => {
+                let (orientation, dagger_level) = match entity {
+                    EntityData::Dagger(dagger) => (
+                        Some(UpdateOrientationData {
+                            a: dagger.orientationa,
+                            b: dagger.orientationb,
+                            c: dagger.orientationc,
+                        }),
+                        Some(dagger.dagger_level.clone()),
+                    ),
+                    _ => (None, None),
+                };
+                let id = self.next_id;
+                self.next_id += 1;
+                self.entities.insert(id, EntityState {
+                    id,
+                    entity_type: entity.entity_type(),
+                    enemy_type: EnemyType::from_entity(entity),
+                    position: entity.position(),
+                    orientation,
+                    target: None,
+                    dagger_level,
+                    transmuted: false,
+                    weak_spot_hits: 0,
+                });
+            },
+            Some(LifecycleEvent::Despawn(id)) => {
+                self.entities.remove(&id);
+            },
+            Some(LifecycleEvent::WeakSpotHit(id)) => {
+                if let Some(enemy) = self.entities.get_mut(&id) {
+                    if count_weak_spot_hit(enemy.enemy_type, &mut enemy.weak_spot_hits) {
+                        self.entities.remove(&id);
+                    }
+                }
+            },
+            None => match event {
+                ReplayEvent::UpdateEntityPosition(id, position) => {
+                    if let Some(entity) = self.entities.get_mut(id) {
+                        entity.position = Some(EntityPosition::Fixed(*position));
+                    }
+                },
+                ReplayEvent::UpdateEntityOrientation(id, orientation) => {
+                    if let Some(entity) = self.entities.get_mut(id) {
+                        entity.orientation = Some(orientation.clone());
+                    }
+                },
+                ReplayEvent::UpdateEntityTarget(id, target) => {
+                    if let Some(entity) = self.entities.get_mut(id) {
+                        entity.target = Some(*target);
+                    }
+                },
+                ReplayEvent::Transmute(id, _) => {
+                    if let Some(entity) = self.entities.get_mut(id) {
+                        entity.transmuted = true;
+                    }
+                },
+                _ => {},
+            },
+        }
+    }
+}
+
+impl ReplayData {
+    pub fn state(&self) -> ReplayState<'_> {
+        ReplayState::new(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::replay::tests::{extend_test_data, skull_spawn, test_data};
+
+    #[test]
+    fn follows_the_event_stream() {
+        let data = test_data();
+        let mut state = data.state();
+        assert_eq!(state.entities().count(), 0);
+
+        assert!(state.step());
+        assert_eq!(state.entities().count(), 3);
+        assert_eq!(state.entity(2).unwrap().dagger_level, Some(DaggerLevel::Level3));
+        assert_eq!(state.entity(3).unwrap().position, Some(EntityPosition::Fixed([100, 200, 300])));
+
+        assert!(state.step());
+        let squid = state.entity(1).unwrap();
+        assert_eq!(squid.position, Some(EntityPosition::Fixed([4, 5, 6])));
+        assert!(squid.transmuted && squid.target.is_some() && squid.orientation.is_some());
+        assert!(state.entity(2).is_none());
+        assert_eq!(state.daggers().count(), 0);
+        assert_eq!(state.enemies().count(), 2);
+
+        assert!(state.step());
+        assert!(!state.step());
+        assert_eq!(state.frame(), 3);
+    }
+
+    #[test]
+    fn seek_matches_stepping() {
+        // A skull every 7 frames, each one moved every frame and killed with
+        // a single hit 50 frames later
+        let data = extend_test_data(2000, |frame| {
+            let mut events = vec![];
+            if frame % 7 == 0 {
+                events.push(skull_spawn(BoidType::Skull1));
+            }
+            let last_skull = 3 + frame as EntityId / 7;
+            for id in 4..=last_skull {
+                events.push(ReplayEvent::UpdateEntityPosition(id, [frame as i16, id as i16, 0]));
+            }
+            if frame >= 57 && frame % 7 == 1 {
+                let enemy_id = 3 + (frame as EntityId - 50) / 7;
+                events.push(ReplayEvent::EnemyHitWeakSpot(EnemyHitData { enemy_id, dagger_id: 2, segment: 0 }));
+            }
+            events
+        });
+
+        let snapshot = |state: &ReplayState| state.entities().cloned().collect::<Vec<_>>();
+        let mut stepped = data.state();
+        let mut expected = vec![];
+        loop {
+            expected.push(snapshot(&stepped));
+            if !stepped.step() {
+                break;
+            }
+        }
+        assert!(expected[1500].len() > 1);
+
+        let mut state = data.state();
+        for frame in [1500, 3, 1999, 600, 599, 1201, 2000, 0, 1800, 5000] {
+            state.seek(frame);
+            let frame = frame.min(2000);
+            assert_eq!(state.frame(), frame);
+            assert_eq!(snapshot(&state), expected[frame]);
+        }
+    }
+
+    #[test]
+    fn agrees_with_the_lifecycles() {
+        let hit = ReplayEvent::EnemyHitWeakSpot(EnemyHitData { enemy_id: 3, dagger_id: 2, segment: 0 });
+        let mut data = test_data();
+        data.frames[1].events.splice(0..0, vec![hit; 6]);
+
+        let lifecycles = data.entity_lifecycles();
+        let mut state = data.state();
+        for frame in 0..data.frames.len() {
+            let alive = lifecycles.iter().filter(|entity| entity.is_alive_at(frame)).map(|entity| entity.id).collect::<Vec<_>>();
+            state.step();
+            assert_eq!(state.entities().map(|entity| entity.id).collect::<Vec<_>>(), alive);
+        }
+    }
+}
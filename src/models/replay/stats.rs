@@ -0,0 +1,237 @@
+//
+// offline run statistics
+//
+
+use crate::models::{GameStatus, StatsBlockWithFrames, StatsDataBlock, StatsFrame};
+use super::progression::HandState;
+use super::*;
+
+/// The stats stored in a `.ddreplay` header, counted again from the events.
+/// `kills` is estimated the same way as `StatsFrame::kills` and `death_type`
+/// is `None` if the replay has no `PlayerDeath` event.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HeaderStats {
+    pub time: f32,
+    pub daggers_fired: u32,
+    pub death_type: Option<i32>,
+    pub gems: u32,
+    pub kills: u32,
+    pub daggers_hit: u32,
+}
+
+struct StatsSeries {
+    frames: Vec<StatsFrame>,
+    level_up_times: [f32; 3], // In game timer like `LevelUp::time`
+    starting_homing: i32,
+}
+
+impl ReplayData {
+    /// Rebuilds the per second stats the game records during a run, the same
+    /// series `StatsBlockWithFrames::frames` holds for live runs.
+    ///
+    /// Frame `i` holds the totals after `i + 1` seconds, a run that doesn't
+    /// end on a whole second gets one more frame for its last partial second.
+    /// Kills come from `EntityLifecycle::death_frame` so they are estimates,
+    /// and gems that despawned or were eaten aren't recorded in replays, so
+    /// `gems_despawned`, `gems_eaten`, `gems_total` and `daggers_eaten` stay 0.
+    pub fn stats_frames(&self, initial_hand: u8, additional_gems: i32) -> Vec<StatsFrame> {
+        self.stats_series(initial_hand, additional_gems, 0.).frames
+    }
+
+    pub fn header_stats(&self) -> HeaderStats {
+        let mut stats = HeaderStats { time: 0., daggers_fired: 0, death_type: None, gems: 0, kills: 0, daggers_hit: 0 };
+        let mut end_frames = 0;
+        for event in self.frames.iter().flat_map(|frame| &frame.events) {
+            match event {
+                ReplayEvent::EndFrame(_, _) => end_frames += 1,
+                ReplayEvent::GemPickup => stats.gems += 1,
+                ReplayEvent::Spawn(EntityData::Dagger(_)) => stats.daggers_fired += 1,
+                ReplayEvent::EnemyHitWeakSpot(_) | ReplayEvent::EnemyHitArmor(_) => stats.daggers_hit += 1,
+                ReplayEvent::PlayerDeath(death) => stats.death_type = Some(death.death_type),
+                _ => {},
+            }
+        }
+        stats.time = end_frames as f32 / FRAMES_PER_SECOND as f32;
+        stats.kills = self.entity_lifecycles().iter()
+            .filter(|entity| entity.enemy_type.is_some() && entity.death_frame.is_some())
+            .count() as u32;
+        stats
+    }
+
+    fn stats_series(&self, initial_hand: u8, additional_gems: i32, timer_start: f32) -> StatsSeries {
+        let mut alive_delta = vec![[0i16; 17]; self.frames.len()];
+        let mut killed = vec![[0i16; 17]; self.frames.len()];
+        for entity in self.entity_lifecycles() {
+            let Some(enemy_type) = entity.enemy_type else { continue };
+            alive_delta[entity.spawn_frame][enemy_type.stats_index()] += 1;
+            if let Some(death) = entity.death_frame {
+                alive_delta[death][enemy_type.stats_index()] -= 1;
+                killed[death][enemy_type.stats_index()] += 1;
+            }
+        }
+
+        let mut hand = HandState::new(initial_hand, additional_gems);
+        let starting_homing = hand.homing;
+        let mut level_up_times = [0.; 3];
+        let mut current = StatsFrame::default();
+        let mut frames = Vec::with_capacity(self.frames.len().div_ceil(FRAMES_PER_SECOND));
+        for (frame, replay_frame) in self.frames.iter().enumerate() {
+            for event in &replay_frame.events {
+                match event {
+                    ReplayEvent::GemPickup => current.gems_collected += 1,
+                    ReplayEvent::Spawn(EntityData::Dagger(_)) => current.daggers_fired += 1,
+                    ReplayEvent::EnemyHitWeakSpot(_) | ReplayEvent::EnemyHitArmor(_) => current.daggers_hit += 1,
+                    _ => {},
+                }
+                if let Some(level) = hand.apply(event) {
+                    level_up_times[level as usize - 2] = timer_start + (frame + 1) as f32 / FRAMES_PER_SECOND as f32;
+                }
+            }
+
+            for i in 0..17 {
+                current.per_enemy_alive_count[i] += alive_delta[frame][i];
+                current.per_enemy_kill_count[i] += killed[frame][i];
+            }
+
+            if (frame + 1) % FRAMES_PER_SECOND == 0 || frame + 1 == self.frames.len() {
+                current.enemies_alive = current.per_enemy_alive_count.iter().map(|&n| n as i32).sum();
+                current.kills = current.per_enemy_kill_count.iter().map(|&n| n as i32).sum();
+                current.level_gems = hand.level_gems;
+                current.homing = hand.homing;
+                frames.push(current);
+            }
+        }
+
+        StatsSeries { frames, level_up_times, starting_homing }
+    }
+}
+
+impl DdRpl {
+    /// Stats for the whole run in the same shape as the ones read from game
+    /// memory, see `ReplayData::stats_frames` for what can't be recovered
+    pub fn stats(&mut self) -> Result<StatsBlockWithFrames> {
+        if self.data.is_none() {
+            self.calc_data()?;
+        }
+
+        let settings = self.spawnset()?.settings().cloned();
+        let settings = settings.as_ref();
+        let initial_hand = settings.map_or(1, |s| s.initial_hand);
+        let additional_gems = settings.map_or(0, |s| s.additional_gems);
+        let timer_start = settings.and_then(|s| s.timer_start).unwrap_or(0.);
+        let series = self.data.as_ref().unwrap().stats_series(initial_hand, additional_gems, timer_start);
+        let last = series.frames.last().copied().unwrap_or_default();
+
+        let mut username = [0u8; 32];
+        let name = self.header.player_name.as_bytes();
+        let len = name.len().min(username.len());
+        username[..len].copy_from_slice(&name[..len]);
+
+        let mut survival_md5 = [0u8; 16];
+        if let Ok(hash) = crate::utils::decode_hex(&self.header.spawnset_hash) {
+            if hash.len() == survival_md5.len() {
+                survival_md5.copy_from_slice(&hash);
+            }
+        }
+
+        let (max_homing_second, max_homing) = series.frames.iter()
+            .enumerate()
+            .fold((0, series.starting_homing), |max, (i, frame)| if frame.homing > max.1 { (i + 1, frame.homing) } else { max });
+        let (max_alive_second, enemies_alive_max) = series.frames.iter()
+            .enumerate()
+            .fold((0, 0), |max, (i, frame)| if frame.enemies_alive > max.1 { (i + 1, frame.enemies_alive) } else { max });
+
+        let block = StatsDataBlock {
+            player_id: self.header.player_id,
+            username,
+            time: self.header.time,
+            gems_collected: last.gems_collected,
+            kills: last.kills,
+            daggers_fired: last.daggers_fired,
+            daggers_hit: last.daggers_hit,
+            enemies_alive: last.enemies_alive,
+            level_gems: last.level_gems,
+            homing: last.homing,
+            per_enemy_alive_count: last.per_enemy_alive_count,
+            per_enemy_kill_count: last.per_enemy_kill_count,
+            is_replay: true,
+            death_type: self.header.death_type as u8,
+            replay_player_id: self.header.player_id,
+            replay_player_name: username,
+            survival_md5,
+            time_lvl2: series.level_up_times[0],
+            time_lvl3: series.level_up_times[1],
+            time_lvl4: series.level_up_times[2],
+            status: GameStatus::LocalReplay as i32,
+            max_homing,
+            time_max_homing: max_homing_second as f32,
+            enemies_alive_max,
+            time_enemies_alive_max: max_alive_second as f32,
+            time_max: self.header.time,
+            stats_frames_loaded: series.frames.len() as i32,
+            stats_finished_loading: true,
+            starting_hand: initial_hand as i32,
+            starting_homing: series.starting_homing,
+            starting_time: self.header.starting_time,
+            ..Default::default()
+        };
+
+        Ok(StatsBlockWithFrames { block, frames: series.frames })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::replay::tests::{extend_test_data, test_data};
+    use crate::models::spawnset::Settings;
+
+    #[test]
+    fn per_second_series() {
+        // test_data plus a gem every other frame and a skull killed at frame 100
+        let hit = ReplayEvent::EnemyHitWeakSpot(EnemyHitData { enemy_id: 3, dagger_id: 2, segment: 0 });
+        let data = extend_test_data(150, |frame| {
+            let mut events = vec![];
+            if frame % 2 == 0 {
+                events.push(ReplayEvent::GemPickup);
+            }
+            if frame == 100 {
+                events.extend(vec![hit.clone(); 5]);
+            }
+            events
+        });
+
+        let stats = data.header_stats();
+        assert_eq!(stats, HeaderStats { time: 150. / 60., daggers_fired: 1, death_type: None, gems: 75, kills: 1, daggers_hit: 7 });
+        assert_eq!(test_data().header_stats().death_type, Some(3));
+
+        let frames = data.stats_frames(1, 0);
+        assert_eq!(frames.len(), 3);
+
+        let skull2 = EnemyType::Skull2.stats_index();
+        let squid1 = EnemyType::Squid1.stats_index();
+        assert_eq!(frames[0].gems_collected, 30);
+        assert_eq!(frames[0].level_gems, 30);
+        assert_eq!(frames[0].daggers_fired, 1);
+        assert_eq!(frames[0].daggers_hit, 2);
+        assert_eq!(frames[0].enemies_alive, 2);
+        assert_eq!(frames[0].per_enemy_alive_count[skull2], 1);
+        assert_eq!(frames[0].kills, 0);
+
+        assert_eq!(frames[1].enemies_alive, 1);
+        assert_eq!(frames[1].per_enemy_alive_count[squid1], 1);
+        assert_eq!(frames[1].per_enemy_kill_count[skull2], 1);
+        assert_eq!(frames[1].kills, 1);
+        assert_eq!(frames[1].daggers_hit, 7);
+
+        assert_eq!(frames[2].gems_collected, 75);
+        assert_eq!(frames[2].level_gems, 70);
+        assert_eq!(frames[2].homing, 5);
+
+        // Level up times use the in game timer, same as `Progression`
+        let settings = Settings { initial_hand: 1, additional_gems: 0, timer_start: Some(30.) };
+        let series = data.stats_series(1, 0, 30.);
+        let progression = data.progression(Some(&settings));
+        assert_eq!(series.level_up_times[..2], [progression.level_up_time(2).unwrap(), progression.level_up_time(3).unwrap()]);
+    }
+}
@@ -0,0 +1,327 @@
+//
+// replay trimming
+//
+
+use std::collections::{BTreeSet, HashMap};
+use std::ops::Range;
+
+use super::*;
+
+impl ReplayData {
+    /// Cuts the replay down to the given frames.
+    ///
+    /// Entities spawned before the cut that are still alive, or that events
+    /// in the range refer to, are spawned again at the start of the first
+    /// frame with their current position, orientation and target. Entity ids
+    /// are renumbered to match the new spawn order, references to entities
+    /// that weren't carried over, like the spawner of a skull whose squid died
+    /// before the cut, become 0. If the range stops before the end of the
+    /// replay an `EndReplay` frame is added after it.
+    pub fn trim(&self, frames: Range<usize>) -> Result<ReplayData> {
+        if frames.start >= frames.end || frames.end > self.frames.len() {
+            bail!("Invalid frame range {:?} for a replay with {} frames", frames, self.frames.len());
+        }
+
+        let spawns = self.frames[..frames.start].iter()
+            .flat_map(|frame| &frame.events)
+            .filter_map(|event| match event {
+                ReplayEvent::Spawn(entity) => Some(entity),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        let mut state = self.state();
+        state.seek(frames.start);
+        let mut carried = state.entities().map(|entity| entity.id).collect::<BTreeSet<_>>();
+        for event in self.frames[frames.clone()].iter().flat_map(|frame| &frame.events) {
+            carried.extend(referenced_ids(event).into_iter().filter(|&id| id >= 1 && id as usize <= spawns.len()));
+        }
+
+        let mut ids = HashMap::new();
+        for (i, &old_id) in carried.iter().enumerate() {
+            ids.insert(old_id, i as EntityId + 1);
+        }
+
+        let mut respawns = vec![];
+        let mut updates = vec![];
+        for &old_id in &carried {
+            let new_id = ids[&old_id];
+            let live = state.entity(old_id);
+            respawns.push(ReplayEvent::Spawn(respawn(spawns[old_id as usize - 1], live, &ids)));
+            let Some(live) = live else { continue };
+            if let (Some(orientation), false) = (&live.orientation, live.entity_type == EntityType::Dagger) {
+                updates.push(ReplayEvent::UpdateEntityOrientation(new_id, orientation.clone()));
+            }
+            if let Some(target) = live.target {
+                updates.push(ReplayEvent::UpdateEntityTarget(new_id, target));
+            }
+        }
+        respawns.extend(updates);
+
+        let mut old_next_id = spawns.len() as EntityId + 1;
+        let mut next_id = carried.len() as EntityId + 1;
+        let mut look_speed = self.look_speed();
+        let mut trimmed = vec![ReplayFrame { events: respawns }];
+        for (i, frame) in self.frames[frames.clone()].iter().enumerate() {
+            if i > 0 {
+                trimmed.push(ReplayFrame { events: vec![] });
+            }
+            let events = &mut trimmed.last_mut().unwrap().events;
+            for event in &frame.events {
+                let mut event = remap(event, &ids);
+                match &mut event {
+                    ReplayEvent::Spawn(_) => {
+                        ids.insert(old_next_id, next_id);
+                        old_next_id += 1;
+                        next_id += 1;
+                    },
+                    ReplayEvent::EndFrame(_, mouse) => mouse.look_speed = look_speed.take(),
+                    _ => {},
+                }
+                events.push(event);
+            }
+        }
+
+        let trailing_bytes = if frames.end == self.frames.len() {
+            self.trailing_bytes.clone()
+        } else {
+            trimmed.push(ReplayFrame { events: vec![ReplayEvent::EndReplay] });
+            vec![]
+        };
+
+        let entities = trimmed.iter()
+            .flat_map(|frame| &frame.events)
+            .filter_map(|event| match event {
+                ReplayEvent::Spawn(entity) => Some(entity.entity_type()),
+                _ => None,
+            })
+            .enumerate()
+            .map(|(i, entity_type)| Entity { id: i as EntityId + 1, entity_type })
+            .collect();
+
+        Ok(ReplayData { frames: trimmed, entities, trailing_bytes })
+    }
+}
+
+impl DdRpl {
+    /// Trimmed copy of the replay, see `ReplayData::trim`.
+    ///
+    /// The header stats are recounted for the range, `time` becomes the
+    /// length of the new replay and `starting_time` moves forward by the cut.
+    /// `time` and `kills` come from `ReplayData::header_stats` on the trimmed
+    /// events so they match what the new replay contains. Respawned enemies
+    /// start with full hp again, hits they took before the cut aren't carried
+    /// over, so an enemy that was damaged before the cut and killed after it
+    /// doesn't count as a kill.
+    pub fn trim(&mut self, frames: Range<usize>) -> Result<DdRpl> {
+        if self.data.is_none() {
+            self.calc_data()?;
+        }
+
+        let data = self.data.as_ref().unwrap();
+        let trimmed = data.trim(frames.clone())?;
+
+        let stats = trimmed.header_stats();
+        let mut header = self.header.clone();
+        header.time = stats.time;
+        header.starting_time += frames.start as f32 / FRAMES_PER_SECOND as f32;
+        header.kills = stats.kills;
+        // Respawned daggers aren't fired again, count these from the range itself
+        (header.gems, header.daggers_fired, header.daggers_hit) = (0, 0, 0);
+        for event in data.frames[frames].iter().flat_map(|frame| &frame.events) {
+            match event {
+                ReplayEvent::GemPickup => header.gems += 1,
+                ReplayEvent::Spawn(EntityData::Dagger(_)) => header.daggers_fired += 1,
+                ReplayEvent::EnemyHitWeakSpot(_) | ReplayEvent::EnemyHitArmor(_) => header.daggers_hit += 1,
+                _ => {},
+            }
+        }
+
+        let mut replay = DdRpl { header, compressed_data: None, data: Some(trimmed), extra: None };
+        replay.compress_data()?;
+        Ok(replay)
+    }
+
+    /// Same as `DdRpl::trim` with the range in seconds from the start of the replay
+    pub fn trim_time(&mut self, start: f32, end: f32) -> Result<DdRpl> {
+        if self.data.is_none() {
+            self.calc_data()?;
+        }
+
+        let frame_count = self.data.as_ref().unwrap().frames.len();
+        let to_frame = |time: f32| ((time.max(0.) * FRAMES_PER_SECOND as f32).round() as usize).min(frame_count);
+        self.trim(to_frame(start)..to_frame(end))
+    }
+}
+
+fn referenced_ids(event: &ReplayEvent) -> Vec<EntityId> {
+    match event {
+        ReplayEvent::Spawn(EntityData::Boid(boid)) => vec![boid.spanwer],
+        ReplayEvent::Spawn(EntityData::Egg(egg)) => vec![egg.spider_spawner],
+        ReplayEvent::UpdateEntityPosition(id, _)
+        | ReplayEvent::UpdateEntityOrientation(id, _)
+        | ReplayEvent::UpdateEntityTarget(id, _)
+        | ReplayEvent::Transmute(id, _) => vec![*id],
+        ReplayEvent::DaggerDewspawn(despawn) => vec![despawn.dagger_id],
+        ReplayEvent::EnemyHitWeakSpot(hit) | ReplayEvent::EnemyHitArmor(hit) => vec![hit.enemy_id, hit.dagger_id],
+        _ => vec![],
+    }
+}
+
+// Ids that aren't in the new replay would point at whatever entity ends up with that id
+fn map_id(ids: &HashMap<EntityId, EntityId>, id: EntityId) -> EntityId {
+    ids.get(&id).copied().unwrap_or(0)
+}
+
+fn remap(event: &ReplayEvent, ids: &HashMap<EntityId, EntityId>) -> ReplayEvent {
+    let mut event = event.clone();
+    match &mut event {
+        ReplayEvent::Spawn(EntityData::Boid(boid)) => boid.spanwer = map_id(ids, boid.spanwer),
+        ReplayEvent::Spawn(EntityData::Egg(egg)) => egg.spider_spawner = map_id(ids, egg.spider_spawner),
+        ReplayEvent::UpdateEntityPosition(id, _)
+        | ReplayEvent::UpdateEntityOrientation(id, _)
+        | ReplayEvent::UpdateEntityTarget(id, _)
+        | ReplayEvent::Transmute(id, _) => *id = map_id(ids, *id),
+        ReplayEvent::DaggerDewspawn(despawn) => despawn.dagger_id = map_id(ids, despawn.dagger_id),
+        ReplayEvent::EnemyHitWeakSpot(hit) | ReplayEvent::EnemyHitArmor(hit) => {
+            hit.enemy_id = map_id(ids, hit.enemy_id);
+            hit.dagger_id = map_id(ids, hit.dagger_id);
+        },
+        _ => {},
+    }
+    event
+}
+
+// Spawn event for an entity carried over the cut, moved to where it is now
+fn respawn(entity: &EntityData, live: Option<&EntityState>, ids: &HashMap<EntityId, EntityId>) -> EntityData {
+    let ReplayEvent::Spawn(mut entity) = remap(&ReplayEvent::Spawn(entity.clone()), ids) else {
+        unreachable!()
+    };
+    let Some(live) = live else { return entity };
+
+    let fixed = |position: &EntityPosition| match position {
+        EntityPosition::Fixed(position) => *position,
+        EntityPosition::Float(position) => position.map(|x| (x * POSITION_SCALE).round() as i16),
+    };
+    let float = |position: &EntityPosition| match position {
+        EntityPosition::Fixed(position) => Vec3::from_fixed(position).into(),
+        EntityPosition::Float(position) => *position,
+    };
+
+    if let Some(position) = &live.position {
+        match &mut entity {
+            EntityData::Dagger(dagger) => dagger.position = fixed(position),
+            EntityData::Boid(boid) => boid.position = fixed(position),
+            EntityData::Squid1(squid) | EntityData::Squid2(squid) | EntityData::Squid3(squid) => squid.position = float(position),
+            EntityData::Centipede(pede) | EntityData::Gigapede(pede) | EntityData::Ghostpede(pede) => pede.position = float(position),
+            EntityData::Spider1(spider) | EntityData::Spider2(spider) => spider.position = float(position),
+            EntityData::Thorn(thorn) => thorn.position = float(position),
+            EntityData::Egg(_) | EntityData::Leviathan(_) => {},
+        }
+    }
+    if let (EntityData::Dagger(dagger), Some(orientation)) = (&mut entity, &live.orientation) {
+        dagger.orientationa = orientation.a;
+        dagger.orientationb = orientation.b;
+        dagger.orientationc = orientation.c;
+    }
+    entity
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::replay::tests::{extend_test_data, test_data, test_replay};
+
+    // test_data with its death frame moved back to frame 10
+    fn long_data() -> ReplayData {
+        let mut data = extend_test_data(10, |frame| match frame {
+            4 => vec![test_data().frames[0].events[1].clone()],
+            6 => vec![ReplayEvent::EnemyHitWeakSpot(EnemyHitData { enemy_id: 3, dagger_id: 4, segment: 0 })],
+            7 => vec![ReplayEvent::GemPickup],
+            8 => vec![ReplayEvent::UpdateEntityPosition(3, [7, 7, 7])],
+            _ => vec![],
+        });
+        data.frames.push(test_data().frames.pop().unwrap());
+        data
+    }
+
+    #[test]
+    fn respawns_entities_at_the_cut() {
+        let data = long_data();
+        let trimmed = data.trim(5..9).unwrap();
+        assert_eq!(trimmed.frames.len(), 5);
+        assert_eq!(trimmed.frames.last().unwrap().events, vec![ReplayEvent::EndReplay]);
+
+        // squid 1, skull 3 and dagger 4 are alive, dagger 2 despawned before the cut
+        let first = &trimmed.frames[0].events;
+        let ReplayEvent::Spawn(EntityData::Squid1(squid)) = &first[0] else { panic!() };
+        assert_eq!(squid.position, [4. / 16., 5. / 16., 6. / 16.]);
+        let ReplayEvent::Spawn(EntityData::Boid(skull)) = &first[1] else { panic!() };
+        assert_eq!(skull.spanwer, 1);
+        assert!(matches!(first[2], ReplayEvent::Spawn(EntityData::Dagger(_))));
+        assert!(matches!(first[3], ReplayEvent::UpdateEntityOrientation(1, _)));
+        assert!(matches!(first[4], ReplayEvent::UpdateEntityTarget(1, [-1, -2, -3])));
+        let ReplayEvent::EndFrame(_, mouse) = first.last().unwrap() else { panic!() };
+        assert_eq!(mouse.look_speed, Some(2.3));
+
+        assert_eq!(trimmed.frames[1].events[0], ReplayEvent::EnemyHitWeakSpot(EnemyHitData { enemy_id: 2, dagger_id: 3, segment: 0 }));
+        assert_eq!(trimmed.frames[3].events[0], ReplayEvent::UpdateEntityPosition(2, [7, 7, 7]));
+        assert_eq!(trimmed.entities.len(), 3);
+
+        assert_eq!(data.trim(0..data.frames.len()).unwrap().frames, data.frames);
+        assert!(data.trim(4..4).is_err() && data.trim(0..100).is_err());
+    }
+
+    #[test]
+    fn trimmed_header_stats() {
+        let mut replay = test_replay();
+        replay.data = Some(long_data());
+        replay.compressed_data = None;
+        let mut trimmed = replay.trim_time(5. / 60., 9. / 60.).unwrap();
+        assert_eq!((trimmed.header.gems, trimmed.header.daggers_fired, trimmed.header.daggers_hit), (1, 0, 1));
+        assert_eq!(trimmed.header.time, 4. / 60.);
+        assert_eq!(trimmed.header.starting_time, 5. / 60.);
+
+        let mut written = vec![];
+        trimmed.write_to(&mut written).unwrap();
+        let mut parsed = DdRpl::from_reader(&mut &written[..]).unwrap();
+        parsed.calc_data().unwrap();
+        trimmed.data.take();
+        trimmed.calc_data().unwrap();
+        assert_eq!(parsed.data, trimmed.data);
+    }
+
+    #[test]
+    fn enemies_damaged_before_the_cut() {
+        // Skull II has 5 hp, 3 hits before the cut and 2 after kill it at frame 7
+        let hit = ReplayEvent::EnemyHitWeakSpot(EnemyHitData { enemy_id: 3, dagger_id: 4, segment: 0 });
+        let mut data = long_data();
+        data.frames[4].events.splice(0..0, vec![hit.clone(); 3]);
+        data.frames[7].events.insert(0, hit);
+
+        let mut replay = test_replay();
+        replay.data = Some(data);
+        replay.compressed_data = None;
+        let trimmed = replay.trim(5..9).unwrap();
+        let stats = trimmed.data.as_ref().unwrap().header_stats();
+        assert_eq!((trimmed.header.time, trimmed.header.kills), (stats.time, stats.kills));
+        assert_eq!(trimmed.header.kills, 0);
+        assert_eq!(trimmed.header.daggers_hit, 2);
+    }
+
+    #[test]
+    fn spawner_died_before_the_cut() {
+        // Squid I has 10 hp, the skull it spawned outlives it
+        let hit = ReplayEvent::EnemyHitWeakSpot(EnemyHitData { enemy_id: 1, dagger_id: 4, segment: 0 });
+        let mut data = long_data();
+        data.frames[4].events.splice(0..0, vec![hit; 10]);
+
+        let trimmed = data.trim(5..9).unwrap();
+        let first = &trimmed.frames[0].events;
+        let ReplayEvent::Spawn(EntityData::Boid(skull)) = &first[0] else { panic!() };
+        assert_eq!(skull.spanwer, 0);
+        assert!(matches!(first[1], ReplayEvent::Spawn(EntityData::Dagger(_))));
+        assert_eq!(trimmed.entities.len(), 2);
+        assert_eq!(trimmed.frames[1].events[0], ReplayEvent::EnemyHitWeakSpot(EnemyHitData { enemy_id: 1, dagger_id: 2, segment: 0 }));
+    }
+}
@@ -0,0 +1,122 @@
+//
+// header statistics validation
+//
+
+use super::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HeaderField {
+    Time,
+    DaggersFired,
+    DeathType,
+    Gems,
+    Kills,
+    DaggersHit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StatMismatch {
+    pub field: HeaderField,
+    pub header: f64,
+    pub replay: f64,
+}
+
+/// How far header values may be from the recounted ones before they're
+/// reported. Kills are only an estimate, see `EntityLifecycle`, and honest
+/// replays routinely miss it, so they're only checked when `kill_tolerance`
+/// is set. The default leaves them out.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ConsistencyOptions {
+    pub time_tolerance: f32,
+    pub kill_tolerance: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConsistencyReport {
+    pub recounted: HeaderStats,
+    pub mismatches: Vec<StatMismatch>,
+}
+
+impl Default for ConsistencyOptions {
+    fn default() -> Self {
+        Self { time_tolerance: 1. / FRAMES_PER_SECOND as f32, kill_tolerance: None }
+    }
+}
+
+impl ConsistencyReport {
+    pub fn is_consistent(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+
+    pub fn mismatch(&self, field: HeaderField) -> Option<&StatMismatch> {
+        self.mismatches.iter().find(|mismatch| mismatch.field == field)
+    }
+}
+
+impl DdRplHeader {
+    /// Compares the header against stats counted from the events, the death
+    /// type is only checked if the replay has a `PlayerDeath` event
+    pub fn check_stats(&self, recounted: HeaderStats, options: &ConsistencyOptions) -> ConsistencyReport {
+        let mut mismatches = vec![];
+        let mut check = |field, header: f64, replay: f64, tolerance: f64| {
+            if (header - replay).abs() > tolerance {
+                mismatches.push(StatMismatch { field, header, replay });
+            }
+        };
+
+        // Frame times don't add up exactly as floats, allow for that on top of the tolerance
+        check(HeaderField::Time, self.time as f64, recounted.time as f64, options.time_tolerance as f64 + 1e-4);
+        check(HeaderField::DaggersFired, self.daggers_fired as f64, recounted.daggers_fired as f64, 0.);
+        if let Some(death_type) = recounted.death_type {
+            check(HeaderField::DeathType, self.death_type as f64, death_type as f64, 0.);
+        }
+        check(HeaderField::Gems, self.gems as f64, recounted.gems as f64, 0.);
+        if let Some(kill_tolerance) = options.kill_tolerance {
+            check(HeaderField::Kills, self.kills as f64, recounted.kills as f64, kill_tolerance as f64);
+        }
+        check(HeaderField::DaggersHit, self.daggers_hit as f64, recounted.daggers_hit as f64, 0.);
+
+        ConsistencyReport { recounted, mismatches }
+    }
+}
+
+impl DdRpl {
+    /// Recounts the header stats from the event stream and reports every
+    /// value that doesn't match, a tampered header shows up here
+    pub fn check_consistency(&mut self, options: &ConsistencyOptions) -> Result<ConsistencyReport> {
+        if self.data.is_none() {
+            self.calc_data()?;
+        }
+
+        let recounted = self.data.as_ref().unwrap().header_stats();
+        Ok(self.header.check_stats(recounted, options))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::replay::tests::test_replay;
+
+    #[test]
+    fn reports_tampered_headers() {
+        let mut replay = test_replay();
+        let report = replay.check_consistency(&ConsistencyOptions::default()).unwrap();
+        let fields = report.mismatches.iter().map(|mismatch| mismatch.field).collect::<Vec<_>>();
+        assert_eq!(fields, vec![HeaderField::DaggersFired, HeaderField::DeathType, HeaderField::Gems, HeaderField::DaggersHit]);
+        assert_eq!(report.mismatch(HeaderField::DaggersHit), Some(&StatMismatch { field: HeaderField::DaggersHit, header: 0., replay: 2. }));
+
+        let header = &mut replay.header;
+        (header.daggers_fired, header.death_type, header.gems, header.daggers_hit) = (1, 3, 1, 2);
+        assert!(replay.check_consistency(&ConsistencyOptions::default()).unwrap().is_consistent());
+
+        replay.header.time = 500.;
+        replay.header.kills = 2;
+        let report = replay.check_consistency(&ConsistencyOptions::default()).unwrap();
+        assert_eq!(report.mismatches.iter().map(|mismatch| mismatch.field).collect::<Vec<_>>(), vec![HeaderField::Time]);
+        let options = ConsistencyOptions { kill_tolerance: Some(0), ..Default::default() };
+        assert_eq!(replay.check_consistency(&options).unwrap().mismatches.len(), 2);
+        let options = ConsistencyOptions { time_tolerance: 500., kill_tolerance: Some(2) };
+        assert!(replay.check_consistency(&options).unwrap().is_consistent());
+    }
+}
@@ -0,0 +1,220 @@
+//
+// building and editing spawnsets
+//
+
+use super::*;
+
+/// Fluent way of making a spawnset, `build` fills in `spawn_count` and only
+/// writes the settings the spawn version supports.
+///
+/// Problems are only reported by `build`, so calls can be chained freely.
+#[derive(Debug, Clone)]
+pub struct SpawnsetBuilder<SpawnType> {
+    spawnset: Spawnset<SpawnType>,
+    settings: Settings,
+    invalid_tiles: Vec<(u16, u16)>,
+}
+
+impl<SpawnType: Copy + Default> Default for SpawnsetBuilder<SpawnType> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<SpawnType: Copy + Default> SpawnsetBuilder<SpawnType> {
+    /// Empty arena and no spawns, level 1 hand and the timer starting at 0
+    pub fn new() -> Self {
+        Self {
+            spawnset: Spawnset {
+                header: Header::default(),
+                arena: Arena::default(),
+                spawns_header: SpawnsHeader::default(),
+                spawns: vec![],
+                settings: None,
+            },
+            settings: Settings { initial_hand: 1, additional_gems: 0, timer_start: Some(0.) },
+            invalid_tiles: vec![],
+        }
+    }
+
+    pub fn add_spawn(mut self, enemy_type: SpawnType, delay: f32) -> Self {
+        self.spawnset.spawns.push(Spawn { enemy_type, delay, ..Default::default() });
+        self
+    }
+
+    /// An `Empty` spawn, the last one marks the start of the end loop
+    pub fn add_wave_separator(self, delay: f32) -> Self {
+        self.add_spawn(SpawnType::default(), delay)
+    }
+
+    pub fn set_spawn_version(mut self, spawn_version: i32) -> Self {
+        self.spawnset.header.spawn_version = spawn_version;
+        self
+    }
+
+    pub fn set_hand(mut self, initial_hand: u8) -> Self {
+        self.settings.initial_hand = initial_hand;
+        self
+    }
+
+    pub fn set_additional_gems(mut self, additional_gems: i32) -> Self {
+        self.settings.additional_gems = additional_gems;
+        self
+    }
+
+    pub fn set_timer_start(mut self, timer_start: f32) -> Self {
+        self.settings.timer_start = Some(timer_start);
+        self
+    }
+
+    pub fn set_dagger_times(mut self, bronze: i32, silver: i32, gold: i32, devil: i32) -> Self {
+        let header = &mut self.spawnset.spawns_header;
+        (header.bronze_dagger_time, header.silver_dagger_time, header.gold_dagger_time, header.devil_dagger_time) = (bronze, silver, gold, devil);
+        self
+    }
+
+    pub fn set_shrink(mut self, start_radius: f32, end_radius: f32, rate: f32) -> Self {
+        let header = &mut self.spawnset.header;
+        (header.shrink_start_radius, header.shrink_end_radius, header.shrink_rate) = (start_radius, end_radius, rate);
+        self
+    }
+
+    pub fn set_arena_tile(mut self, x: u16, y: u16, height: f32) -> Self {
+        if x < ARENA_SIZE && y < ARENA_SIZE {
+            *self.spawnset.arena.get_tile_mut(x, y) = height;
+        } else {
+            self.invalid_tiles.push((x, y));
+        }
+        self
+    }
+
+    /// Sets every tile whose center is within `radius` tiles of `(x, y)`, parts outside of the arena are left out
+    pub fn fill_circle(mut self, x: f32, y: f32, radius: f32, height: f32) -> Self {
+        for tile_y in 0..ARENA_SIZE {
+            for tile_x in 0..ARENA_SIZE {
+                if (tile_x as f32 - x).hypot(tile_y as f32 - y) <= radius {
+                    *self.spawnset.arena.get_tile_mut(tile_x, tile_y) = height;
+                }
+            }
+        }
+        self
+    }
+
+    pub fn build(self) -> Result<Spawnset<SpawnType>, SpawnsetBuildError> {
+        let SpawnsetBuilder { mut spawnset, settings, invalid_tiles } = self;
+
+        if let Some(&(x, y)) = invalid_tiles.first() {
+            return Err(SpawnsetBuildError::TileOutOfBounds { x, y });
+        }
+        if let Some((spawn, s)) = spawnset.spawns.iter().enumerate().find(|(_, s)| !s.delay.is_finite() || s.delay < 0.) {
+            return Err(SpawnsetBuildError::InvalidDelay { spawn, delay: s.delay });
+        }
+
+        let header = &spawnset.spawns_header;
+        let times = [header.bronze_dagger_time, header.silver_dagger_time, header.gold_dagger_time, header.devil_dagger_time];
+        if times[0] < 0 || times.windows(2).any(|pair| pair[0] > pair[1]) {
+            let [bronze, silver, gold, devil] = times;
+            return Err(SpawnsetBuildError::InvalidDaggerTimes { bronze, silver, gold, devil });
+        }
+
+        if !(1..=4).contains(&settings.initial_hand) {
+            return Err(SpawnsetBuildError::InvalidHand(settings.initial_hand));
+        }
+        if settings.additional_gems < 0 {
+            return Err(SpawnsetBuildError::InvalidAdditionalGems(settings.additional_gems));
+        }
+        let timer_start = settings.timer_start.unwrap_or(0.);
+        if !timer_start.is_finite() || timer_start < 0. {
+            return Err(SpawnsetBuildError::InvalidTimerStart(timer_start));
+        }
+
+        // Older spawn versions have nowhere to store the settings, only the defaults can be kept
+        let spawn_version = spawnset.header.spawn_version;
+        let unsupported = |setting| Err(SpawnsetBuildError::UnsupportedSetting { setting, spawn_version });
+        if spawn_version < 5 && settings.initial_hand != 1 {
+            return unsupported("initial hand");
+        }
+        if spawn_version < 5 && settings.additional_gems != 0 {
+            return unsupported("additional gems");
+        }
+        if spawn_version < 6 && timer_start != 0. {
+            return unsupported("timer start");
+        }
+
+        spawnset.settings = (spawn_version >= 5).then(|| Settings {
+            timer_start: (spawn_version >= 6).then_some(timer_start),
+            ..settings
+        });
+        spawnset.recalculate_spawn_count();
+        Ok(spawnset)
+    }
+}
+
+/// Starts from an existing spawnset to edit it
+impl<SpawnType> From<Spawnset<SpawnType>> for SpawnsetBuilder<SpawnType> {
+    fn from(spawnset: Spawnset<SpawnType>) -> Self {
+        let settings = spawnset.settings.clone().unwrap_or(Settings { initial_hand: 1, additional_gems: 0, timer_start: None });
+        Self { spawnset, settings, invalid_tiles: vec![] }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_consistent_spawnsets() {
+        let spawnset = SpawnsetBuilder::new()
+            .add_spawn(V3Enemies::Squid1, 3.)
+            .add_wave_separator(2.)
+            .add_spawn(V3Enemies::Thorn, 5.)
+            .set_hand(3)
+            .set_additional_gems(20)
+            .set_timer_start(10.)
+            .set_dagger_times(10, 20, 30, 40)
+            .fill_circle(25., 25., 2., 0.)
+            .set_arena_tile(25, 25, 4.)
+            .build()
+            .unwrap();
+
+        assert_eq!(spawnset.spawns_header.spawn_count, 3);
+        assert_eq!(spawnset.spawns[1].enemy_type, V3Enemies::Empty);
+        assert_eq!(spawnset.loop_start(), Some(1));
+        assert_eq!(spawnset.spawns_header.gold_dagger_time, 30);
+        let settings = spawnset.settings.as_ref().unwrap();
+        assert_eq!((settings.initial_hand, settings.additional_gems, settings.timer_start), (3, 20, Some(10.)));
+        assert_eq!(*spawnset.arena.get_tile(25, 25), 4.);
+        assert_eq!(*spawnset.arena.get_tile(27, 25), 0.);
+        assert!(!spawnset.arena.is_solid(27, 27));
+
+        let mut buf = vec![];
+        spawnset.serialize(&mut buf).unwrap();
+        let parsed = Spawnset::<V3Enemies>::deserialize(&mut &buf[..]).unwrap();
+        assert_eq!(parsed.spawns.len(), 3);
+
+        // Editing drops the timer start when going back to a version without it
+        let edited = SpawnsetBuilder::from(parsed).set_timer_start(0.).set_spawn_version(5).build().unwrap();
+        assert_eq!(edited.settings.unwrap().timer_start, None);
+    }
+
+    #[test]
+    fn build_validates() {
+        let build = |builder: SpawnsetBuilder<V3Enemies>| builder.build().unwrap_err();
+        assert_eq!(build(SpawnsetBuilder::new().set_hand(5)), SpawnsetBuildError::InvalidHand(5));
+        assert_eq!(build(SpawnsetBuilder::new().set_additional_gems(-1)), SpawnsetBuildError::InvalidAdditionalGems(-1));
+        assert_eq!(
+            build(SpawnsetBuilder::new().add_spawn(V3Enemies::Squid1, 1.).add_spawn(V3Enemies::Squid2, -1.)),
+            SpawnsetBuildError::InvalidDelay { spawn: 1, delay: -1. }
+        );
+        assert!(matches!(build(SpawnsetBuilder::new().set_dagger_times(60, 50, 250, 500)), SpawnsetBuildError::InvalidDaggerTimes { .. }));
+        assert!(matches!(build(SpawnsetBuilder::new().set_timer_start(f32::NAN)), SpawnsetBuildError::InvalidTimerStart(_)));
+        assert_eq!(build(SpawnsetBuilder::new().set_arena_tile(51, 0, 0.)), SpawnsetBuildError::TileOutOfBounds { x: 51, y: 0 });
+        assert_eq!(
+            build(SpawnsetBuilder::new().set_spawn_version(4).set_hand(2)),
+            SpawnsetBuildError::UnsupportedSetting { setting: "initial hand", spawn_version: 4 }
+        );
+
+        let v4 = SpawnsetBuilder::<V3Enemies>::new().set_spawn_version(4).build().unwrap();
+        assert!(v4.settings.is_none());
+    }
+}
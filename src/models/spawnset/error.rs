@@ -0,0 +1,77 @@
+//
+// spawnset parsing errors
+//
+
+use std::fmt;
+
+#[derive(Debug)]
+pub enum SpawnsetError {
+    Io(std::io::Error),
+    UnexpectedEof { offset: u64, field: &'static str },
+    InvalidSpawnCount { offset: u64, count: i32, available: u64 },
+    InvalidEnemy { offset: u64, spawn: usize, value: i32 },
+}
+
+impl fmt::Display for SpawnsetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpawnsetError::Io(e) => write!(f, "io error: {}", e),
+            SpawnsetError::UnexpectedEof { offset, field } => {
+                write!(f, "unexpected end of data at offset {} while reading {}", offset, field)
+            },
+            SpawnsetError::InvalidSpawnCount { offset, count, available } => {
+                write!(f, "spawn count {} at offset {} doesn't fit in the {} bytes left", count, offset, available)
+            },
+            SpawnsetError::InvalidEnemy { offset, spawn, value } => {
+                write!(f, "invalid enemy type {} for spawn {} at offset {}", value, spawn, offset)
+            },
+        }
+    }
+}
+
+impl std::error::Error for SpawnsetError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SpawnsetError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl std::convert::From<std::io::Error> for SpawnsetError {
+    fn from(e: std::io::Error) -> Self {
+        SpawnsetError::Io(e)
+    }
+}
+
+/// Why `SpawnsetBuilder::build` refused to build a spawnset
+#[derive(Debug, Clone, PartialEq)]
+pub enum SpawnsetBuildError {
+    InvalidDelay { spawn: usize, delay: f32 },
+    InvalidHand(u8),
+    InvalidAdditionalGems(i32),
+    InvalidTimerStart(f32),
+    InvalidDaggerTimes { bronze: i32, silver: i32, gold: i32, devil: i32 },
+    TileOutOfBounds { x: u16, y: u16 },
+    UnsupportedSetting { setting: &'static str, spawn_version: i32 },
+}
+
+impl fmt::Display for SpawnsetBuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpawnsetBuildError::InvalidDelay { spawn, delay } => write!(f, "invalid delay {} for spawn {}", delay, spawn),
+            SpawnsetBuildError::InvalidHand(hand) => write!(f, "invalid hand level {}, it has to be 1 to 4", hand),
+            SpawnsetBuildError::InvalidAdditionalGems(gems) => write!(f, "invalid additional gem count {}", gems),
+            SpawnsetBuildError::InvalidTimerStart(time) => write!(f, "invalid timer start {}", time),
+            SpawnsetBuildError::InvalidDaggerTimes { bronze, silver, gold, devil } => {
+                write!(f, "dagger times {} / {} / {} / {} aren't increasing", bronze, silver, gold, devil)
+            },
+            SpawnsetBuildError::TileOutOfBounds { x, y } => write!(f, "tile ({}, {}) is outside of the arena", x, y),
+            SpawnsetBuildError::UnsupportedSetting { setting, spawn_version } => {
+                write!(f, "spawn version {} spawnsets can't set the {}", spawn_version, setting)
+            },
+        }
+    }
+}
+
+impl std::error::Error for SpawnsetBuildError {}
@@ -0,0 +1,549 @@
+//
+// spawnsets
+//
+
+use std::{io::{Read, Write}, path::Path};
+use anyhow::Result;
+use num_derive::{FromPrimitive, ToPrimitive};
+use num_traits::{FromPrimitive, ToPrimitive};
+
+mod builder;
+mod error;
+mod shrink;
+mod timeline;
+mod upgrade;
+
+pub use builder::SpawnsetBuilder;
+pub use error::{SpawnsetBuildError, SpawnsetError};
+pub use shrink::{ARENA_SIZE, SOLID_MIN_HEIGHT, TILE_SIZE, VOID_HEIGHT};
+pub use timeline::{SpawnTimeline, TimedSpawn, END_LOOP_SPEEDUP};
+pub use upgrade::SpawnsetVersion;
+
+const ARENA_TILES: usize = 51 * 51;
+const SPAWN_SIZE: u64 = 28;
+
+#[derive(Debug, Clone)]
+pub struct Spawnset<SpawnType> {
+    pub header: Header,
+    pub arena: Arena,
+    pub spawns_header: SpawnsHeader,
+    pub spawns: Vec<Spawn<SpawnType>>,
+    pub settings: Option<Settings>
+}
+
+/// Spawnset of whichever game version it was made for.
+///
+/// There's no V2 variant, V2 and V3 spawnsets use the same spawn and world
+/// versions so a file can't say which of the two it's from. Those are read
+/// as V3 like the current game does, use `Spawnset::<V2Enemies>` and convert
+/// it with `into` for spawnsets known to be from V2.
+#[derive(Debug, Clone)]
+pub enum AnySpawnset {
+    V1(Spawnset<V1Enemies>),
+    V3(Spawnset<V3Enemies>),
+}
+
+#[repr(i32)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, FromPrimitive, ToPrimitive)]
+pub enum V3Enemies {
+    Squid1 = 0,
+    Squid2 = 1,
+    Centipede = 2,
+    Spider1 = 3,
+    Leviathan = 4,
+    Gigapede = 5,
+    Squid3 = 6,
+    Thorn = 7,
+    Spider2 = 8,
+    Ghostpede = 9,
+    #[default]
+    Empty = -1,
+}
+
+#[repr(i32)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, FromPrimitive, ToPrimitive)]
+pub enum V2Enemies {
+    Squid1 = 0,
+    Squid2 = 1,
+    Centipede = 2,
+    Spider1 = 3,
+    Leviathan = 4,
+    Gigapede = 5,
+    Squid3 = 6,
+    Andras = 7,
+    Spider2 = 8,
+    #[default]
+    Empty = -1,
+}
+
+#[repr(i32)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, FromPrimitive, ToPrimitive)]
+pub enum V1Enemies {
+    Squid1 = 0,
+    Squid2 = 1,
+    Centipede = 2,
+    Spider1 = 3,
+    Leviathan = 4,
+    Gigapede = 5,
+    #[default]
+    Empty = -1,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct Spawn<SpawnType> {
+    pub enemy_type: SpawnType,
+    pub delay: f32,
+    pub _u1: u32,
+    pub _u2: u32,
+    pub _u3: u32,
+    pub _u4: u32,
+    pub _u5: u32
+}
+
+#[repr(C)]
+#[derive(Debug, Clone)]
+pub struct Header {
+    pub spawn_version: i32,
+    pub world_version: i32,
+    pub shrink_end_radius: f32,
+    pub shrink_start_radius: f32,
+    pub shrink_rate: f32,
+    pub brightness: f32,
+    pub game_mode: i32,
+    pub _u1: u32,
+    pub _u2: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone)]
+pub struct Arena {
+    pub data: [f32; 51*51],
+}
+
+#[repr(C)]
+#[derive(Debug, Clone)]
+pub struct SpawnsHeader {
+    pub _u1: u32,
+    pub _u2: u32,
+    pub _u3: u32,
+    pub _u4: u32,
+    pub devil_dagger_time: i32,
+    pub gold_dagger_time: i32,
+    pub silver_dagger_time: i32,
+    pub bronze_dagger_time: i32,
+    pub _u5: u32,
+    pub spawn_count: i32,
+}
+
+#[derive(Debug, Clone)]
+pub struct Settings {
+    pub initial_hand: u8,
+    pub additional_gems: i32,
+    pub timer_start: Option<f32>,
+}
+
+///////////////
+/*** IMPLS ***/
+///////////////
+
+impl<T: Default> std::default::Default for Spawn<T> {
+    fn default() -> Self {
+        Spawn {
+            enemy_type: T::default(),
+            delay: 0.0,
+            _u1: 0,
+            _u2: 3,
+            _u3: 0,
+            _u4: 1106247680,
+            _u5: 10,
+        }
+    }
+}
+
+impl std::default::Default for Header {
+    fn default() -> Self {
+        Header {
+            spawn_version: 6,
+            world_version: 9,
+            shrink_end_radius: 20.,
+            shrink_start_radius: 50.,
+            shrink_rate: 0.025,
+            brightness: 60.,
+            game_mode: 0,
+            _u1: 51,
+            _u2: 1,
+        }
+    }
+}
+
+impl std::default::Default for Arena {
+    fn default() -> Self {
+        Arena {
+            data: [VOID_HEIGHT; ARENA_TILES],
+        }
+    }
+}
+
+// Accessors 2D -> 1D
+impl Arena {
+    pub fn get_tile(&self, x: u16, y: u16) -> &f32 {
+        &self.data[y as usize * 51 + x as usize]
+    }
+
+    pub fn get_tile_mut(&mut self, x: u16, y: u16) -> &mut f32 {
+        &mut self.data[y as usize * 51 + x as usize]
+    }
+}
+
+impl std::default::Default for SpawnsHeader {
+    fn default() -> Self {
+        SpawnsHeader {
+            devil_dagger_time: 500,
+            gold_dagger_time: 250,
+            silver_dagger_time: 120,
+            bronze_dagger_time: 60,
+            spawn_count: 0,
+            _u1: 0,
+            _u2: 0,
+            _u3: 0,
+            _u4: 1,
+            _u5: 0,
+        }
+    }
+}
+
+impl std::default::Default for Settings {
+    fn default() -> Self {
+        Settings {
+            additional_gems: 0,
+            initial_hand: 0,
+            timer_start: Some(0.0),
+        }
+    }
+}
+
+/// Little endian reader that keeps track of the offset for errors
+struct SpawnsetReader<R> {
+    inner: R,
+    offset: u64,
+}
+
+impl<R: Read> SpawnsetReader<R> {
+    fn read_array<const N: usize>(&mut self, field: &'static str) -> Result<[u8; N], SpawnsetError> {
+        let mut buf = [0u8; N];
+        self.inner.read_exact(&mut buf).map_err(|e| match e.kind() {
+            std::io::ErrorKind::UnexpectedEof => SpawnsetError::UnexpectedEof { offset: self.offset, field },
+            _ => SpawnsetError::Io(e),
+        })?;
+        self.offset += N as u64;
+        Ok(buf)
+    }
+
+    fn read_u8(&mut self, field: &'static str) -> Result<u8, SpawnsetError> {
+        Ok(self.read_array::<1>(field)?[0])
+    }
+
+    fn read_i32(&mut self, field: &'static str) -> Result<i32, SpawnsetError> {
+        Ok(i32::from_le_bytes(self.read_array(field)?))
+    }
+
+    fn read_u32(&mut self, field: &'static str) -> Result<u32, SpawnsetError> {
+        Ok(u32::from_le_bytes(self.read_array(field)?))
+    }
+
+    fn read_f32(&mut self, field: &'static str) -> Result<f32, SpawnsetError> {
+        Ok(f32::from_le_bytes(self.read_array(field)?))
+    }
+
+    /// Reads through `take` so a corrupt length can't make us allocate more
+    /// than what's actually in the source, returns however many bytes there were
+    fn read_up_to(&mut self, len: u64) -> Result<Vec<u8>, SpawnsetError> {
+        let mut buf = vec![];
+        let read = (&mut self.inner).take(len).read_to_end(&mut buf)?;
+        self.offset += read as u64;
+        Ok(buf)
+    }
+}
+
+impl Header {
+    fn read<R: Read>(r: &mut SpawnsetReader<R>) -> Result<Self, SpawnsetError> {
+        Ok(Header {
+            spawn_version: r.read_i32("spawn version")?,
+            world_version: r.read_i32("world version")?,
+            shrink_end_radius: r.read_f32("shrink end radius")?,
+            shrink_start_radius: r.read_f32("shrink start radius")?,
+            shrink_rate: r.read_f32("shrink rate")?,
+            brightness: r.read_f32("brightness")?,
+            game_mode: r.read_i32("game mode")?,
+            _u1: r.read_u32("header")?,
+            _u2: r.read_u32("header")?,
+        })
+    }
+
+    fn write<W: Write>(&self, sink: &mut W) -> std::io::Result<()> {
+        sink.write_all(&self.spawn_version.to_le_bytes())?;
+        sink.write_all(&self.world_version.to_le_bytes())?;
+        sink.write_all(&self.shrink_end_radius.to_le_bytes())?;
+        sink.write_all(&self.shrink_start_radius.to_le_bytes())?;
+        sink.write_all(&self.shrink_rate.to_le_bytes())?;
+        sink.write_all(&self.brightness.to_le_bytes())?;
+        sink.write_all(&self.game_mode.to_le_bytes())?;
+        sink.write_all(&self._u1.to_le_bytes())?;
+        sink.write_all(&self._u2.to_le_bytes())
+    }
+}
+
+impl SpawnsHeader {
+    fn read<R: Read>(r: &mut SpawnsetReader<R>) -> Result<Self, SpawnsetError> {
+        Ok(SpawnsHeader {
+            _u1: r.read_u32("spawns header")?,
+            _u2: r.read_u32("spawns header")?,
+            _u3: r.read_u32("spawns header")?,
+            _u4: r.read_u32("spawns header")?,
+            devil_dagger_time: r.read_i32("devil dagger time")?,
+            gold_dagger_time: r.read_i32("gold dagger time")?,
+            silver_dagger_time: r.read_i32("silver dagger time")?,
+            bronze_dagger_time: r.read_i32("bronze dagger time")?,
+            _u5: r.read_u32("spawns header")?,
+            spawn_count: r.read_i32("spawn count")?,
+        })
+    }
+
+    fn write<W: Write>(&self, sink: &mut W) -> std::io::Result<()> {
+        for value in [self._u1, self._u2, self._u3, self._u4] {
+            sink.write_all(&value.to_le_bytes())?;
+        }
+        for value in [self.devil_dagger_time, self.gold_dagger_time, self.silver_dagger_time, self.bronze_dagger_time] {
+            sink.write_all(&value.to_le_bytes())?;
+        }
+        sink.write_all(&self._u5.to_le_bytes())?;
+        sink.write_all(&self.spawn_count.to_le_bytes())
+    }
+}
+
+impl<SpawnType: Copy + FromPrimitive + ToPrimitive> Spawnset<SpawnType> {
+    /// Reads a spawnset field by field. Enemy ids that don't exist in
+    /// `SpawnType`'s version and spawn counts bigger than what's left in the
+    /// source are rejected instead of trusted.
+    pub fn deserialize<R: Read>(source: &mut R) -> Result<Self, SpawnsetError> {
+        let mut r = SpawnsetReader { inner: source, offset: 0 };
+        let header = Header::read(&mut r)?;
+        let mut arena = Arena { data: [0.; ARENA_TILES] };
+        for tile in arena.data.iter_mut() {
+            *tile = r.read_f32("arena")?;
+        }
+
+        let spawn_count_offset = r.offset + 36;
+        let spawns_header = SpawnsHeader::read(&mut r)?;
+        let count = spawns_header.spawn_count;
+        let spawns_start = r.offset;
+        let spawns_buf = match u64::try_from(count) {
+            Ok(len) => r.read_up_to(len * SPAWN_SIZE)?,
+            Err(_) => vec![],
+        };
+        if count < 0 || (spawns_buf.len() as u64) < count as u64 * SPAWN_SIZE {
+            return Err(SpawnsetError::InvalidSpawnCount { offset: spawn_count_offset, count, available: spawns_buf.len() as u64 });
+        }
+
+        let mut spawns = Vec::with_capacity(count as usize);
+        let mut spawns_reader = SpawnsetReader { inner: &spawns_buf[..], offset: spawns_start };
+        for i in 0..count as usize {
+            let offset = spawns_reader.offset;
+            let value = spawns_reader.read_i32("enemy type")?;
+            let enemy_type = SpawnType::from_i32(value).ok_or(SpawnsetError::InvalidEnemy { offset, spawn: i, value })?;
+            spawns.push(Spawn {
+                enemy_type,
+                delay: spawns_reader.read_f32("spawn delay")?,
+                _u1: spawns_reader.read_u32("spawn")?,
+                _u2: spawns_reader.read_u32("spawn")?,
+                _u3: spawns_reader.read_u32("spawn")?,
+                _u4: spawns_reader.read_u32("spawn")?,
+                _u5: spawns_reader.read_u32("spawn")?,
+            });
+        }
+
+        let mut settings = None;
+        if header.spawn_version >= 5 {
+            settings = Some(Settings {
+                initial_hand: r.read_u8("initial hand")?,
+                additional_gems: r.read_i32("additional gems")?,
+                timer_start: None,
+            });
+        }
+        if header.spawn_version >= 6 {
+            if let Some(sett) = &mut settings {
+                sett.timer_start = Some(r.read_f32("timer start")?);
+            }
+        }
+
+        Ok(Spawnset {
+            header,
+            arena,
+            spawns_header,
+            spawns,
+            settings,
+        })
+    }
+
+    pub fn serialize<W: Write>(&self, sink: &mut W) -> Result<()> {
+        self.header.write(sink)?;
+        for tile in &self.arena.data {
+            sink.write_all(&tile.to_le_bytes())?;
+        }
+        self.spawns_header.write(sink)?;
+        for spawn in &self.spawns {
+            // Every enemy enum is a repr(i32), this can't fail
+            sink.write_all(&spawn.enemy_type.to_i32().unwrap_or(-1).to_le_bytes())?;
+            sink.write_all(&spawn.delay.to_le_bytes())?;
+            for value in [spawn._u1, spawn._u2, spawn._u3, spawn._u4, spawn._u5] {
+                sink.write_all(&value.to_le_bytes())?;
+            }
+        }
+        if let Some(settings) = &self.settings {
+            if self.header.spawn_version >= 5 {
+                sink.write_all(&settings.initial_hand.to_le_bytes())?;
+                sink.write_all(&settings.additional_gems.to_le_bytes())?;
+            }
+            if let Some(timer_start) = settings.timer_start {
+                if self.header.spawn_version >= 6 {
+                    sink.write_all(&timer_start.to_le_bytes())?;
+                }
+            }
+        }
+        sink.flush()?;
+        Ok(())
+    }
+}
+
+impl<SpawnType> Spawnset<SpawnType> {
+    pub fn recalculate_spawn_count(&mut self) {
+        self.spawns_header.spawn_count = self.spawns.len() as i32;
+    }
+}
+
+impl AnySpawnset {
+    /// Opens a spawnset of any game version, see `AnySpawnset::deserialize`
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, SpawnsetError> {
+        AnySpawnset::deserialize(&mut std::fs::File::open(path)?)
+    }
+
+    /// Reads a spawnset, picking the enemy version from its header
+    pub fn deserialize<R: Read>(source: &mut R) -> Result<Self, SpawnsetError> {
+        let mut buf = vec![];
+        source.read_to_end(&mut buf)?;
+        Ok(match AnySpawnset::detect(&buf) {
+            Some(SpawnsetVersion::V1) => AnySpawnset::V1(Spawnset::deserialize(&mut &buf[..])?),
+            _ => AnySpawnset::V3(Spawnset::deserialize(&mut &buf[..])?),
+        })
+    }
+
+    pub fn serialize<W: Write>(&self, sink: &mut W) -> Result<()> {
+        match self {
+            AnySpawnset::V1(spawnset) => spawnset.serialize(sink),
+            AnySpawnset::V3(spawnset) => spawnset.serialize(sink),
+        }
+    }
+
+    pub fn header(&self) -> &Header {
+        match self {
+            AnySpawnset::V1(spawnset) => &spawnset.header,
+            AnySpawnset::V3(spawnset) => &spawnset.header,
+        }
+    }
+
+    pub fn arena(&self) -> &Arena {
+        match self {
+            AnySpawnset::V1(spawnset) => &spawnset.arena,
+            AnySpawnset::V3(spawnset) => &spawnset.arena,
+        }
+    }
+
+    pub fn spawns_header(&self) -> &SpawnsHeader {
+        match self {
+            AnySpawnset::V1(spawnset) => &spawnset.spawns_header,
+            AnySpawnset::V3(spawnset) => &spawnset.spawns_header,
+        }
+    }
+
+    pub fn settings(&self) -> Option<&Settings> {
+        match self {
+            AnySpawnset::V1(spawnset) => spawnset.settings.as_ref(),
+            AnySpawnset::V3(spawnset) => spawnset.settings.as_ref(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_spawnset() -> Spawnset<V3Enemies> {
+        let mut arena = Arena::default();
+        *arena.get_tile_mut(25, 25) = 0.;
+        *arena.get_tile_mut(3, 40) = -2.5;
+        let mut spawnset = Spawnset {
+            header: Header::default(),
+            arena,
+            spawns_header: SpawnsHeader::default(),
+            spawns: vec![
+                Spawn { enemy_type: V3Enemies::Squid1, delay: 3., ..Default::default() },
+                Spawn { enemy_type: V3Enemies::Empty, delay: 2., ..Default::default() },
+                Spawn { enemy_type: V3Enemies::Ghostpede, delay: 10.5, ..Default::default() },
+            ],
+            settings: Some(Settings { initial_hand: 3, additional_gems: 30, timer_start: Some(12.) }),
+        };
+        spawnset.recalculate_spawn_count();
+        spawnset
+    }
+
+    fn bytes<T: Copy + FromPrimitive + ToPrimitive>(spawnset: &Spawnset<T>) -> Vec<u8> {
+        let mut buf = vec![];
+        spawnset.serialize(&mut buf).unwrap();
+        buf
+    }
+
+    #[test]
+    fn round_trip() {
+        let buf = bytes(&test_spawnset());
+        assert_eq!(buf.len(), 36 + ARENA_TILES * 4 + 40 + 3 * 28 + 9);
+        let parsed = Spawnset::<V3Enemies>::deserialize(&mut &buf[..]).unwrap();
+        assert_eq!(parsed.spawns.iter().map(|s| s.enemy_type).collect::<Vec<_>>(), vec![V3Enemies::Squid1, V3Enemies::Empty, V3Enemies::Ghostpede]);
+        assert_eq!(*parsed.arena.get_tile(3, 40), -2.5);
+        assert_eq!(parsed.settings.as_ref().unwrap().timer_start, Some(12.));
+        assert_eq!(bytes(&parsed), buf);
+    }
+
+    #[test]
+    fn rejects_enemies_from_other_versions() {
+        let buf = bytes(&test_spawnset());
+        let ghostpede_offset = 36 + ARENA_TILES as u64 * 4 + 40 + 2 * 28;
+        assert!(matches!(
+            Spawnset::<V2Enemies>::deserialize(&mut &buf[..]),
+            Err(SpawnsetError::InvalidEnemy { offset, spawn: 2, value: 9 }) if offset == ghostpede_offset
+        ));
+        assert!(Spawnset::<V1Enemies>::deserialize(&mut &buf[..]).is_err());
+    }
+
+    #[test]
+    fn rejects_bad_spawn_counts() {
+        let count_offset = 36 + ARENA_TILES * 4 + 36;
+        for count in [-1, 4, i32::MAX] {
+            let mut buf = bytes(&test_spawnset());
+            buf[count_offset..count_offset + 4].copy_from_slice(&count.to_le_bytes());
+            let result = Spawnset::<V3Enemies>::deserialize(&mut &buf[..]);
+            assert!(
+                matches!(result, Err(SpawnsetError::InvalidSpawnCount { offset, count: c, .. }) if offset == count_offset as u64 && c == count),
+                "accepted spawn count {}", count
+            );
+        }
+    }
+
+    #[test]
+    fn truncated_spawnsets_fail() {
+        let buf = bytes(&test_spawnset());
+        for len in 0..buf.len() {
+            assert!(Spawnset::<V3Enemies>::deserialize(&mut &buf[..len]).is_err(), "accepted spawnset truncated to {} bytes", len);
+        }
+    }
+}
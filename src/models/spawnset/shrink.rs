@@ -0,0 +1,125 @@
+//
+// arena shrinking
+//
+
+use super::*;
+
+pub const ARENA_SIZE: u16 = 51;
+const ARENA_CENTER: f32 = 25.;
+
+/// World units per tile, the shrink radii are in world units
+pub const TILE_SIZE: f32 = 4.;
+
+/// Height of a tile that isn't there, tiles at or below `SOLID_MIN_HEIGHT` count as void
+pub const VOID_HEIGHT: f32 = -1000.;
+pub const SOLID_MIN_HEIGHT: f32 = -1.;
+
+impl Header {
+    /// Radius of the arena `time` seconds into the run, it goes from the
+    /// start radius down to the end radius at `shrink_rate` units per second
+    pub fn shrink_radius_at(&self, time: f32) -> f32 {
+        (self.shrink_start_radius - time.max(0.) * self.shrink_rate).max(self.shrink_end_radius)
+    }
+}
+
+impl Arena {
+    pub fn is_solid(&self, x: u16, y: u16) -> bool {
+        *self.get_tile(x, y) > SOLID_MIN_HEIGHT
+    }
+
+    /// Distance from the center of the arena to the center of the tile, in world units
+    pub fn tile_distance(x: u16, y: u16) -> f32 {
+        (x as f32 - ARENA_CENTER).hypot(y as f32 - ARENA_CENTER) * TILE_SIZE
+    }
+
+    /// When the shrinking arena reaches the tile, `None` if it's void or the arena never gets that small
+    pub fn tile_fall_time(&self, header: &Header, x: u16, y: u16) -> Option<f32> {
+        if !self.is_solid(x, y) {
+            return None;
+        }
+
+        let distance = Self::tile_distance(x, y);
+        if distance >= header.shrink_start_radius {
+            Some(0.)
+        } else if distance < header.shrink_end_radius || header.shrink_rate <= 0. {
+            None
+        } else {
+            Some((header.shrink_start_radius - distance) / header.shrink_rate)
+        }
+    }
+
+    /// The arena `time` seconds into the run, tiles the shrink reached are void
+    pub fn at(&self, header: &Header, time: f32) -> Arena {
+        let radius = header.shrink_radius_at(time);
+        let mut arena = self.clone();
+        for y in 0..ARENA_SIZE {
+            for x in 0..ARENA_SIZE {
+                if Self::tile_distance(x, y) >= radius {
+                    *arena.get_tile_mut(x, y) = VOID_HEIGHT;
+                }
+            }
+        }
+        arena
+    }
+}
+
+impl<SpawnType> Spawnset<SpawnType> {
+    /// See `Arena::at`
+    pub fn arena_at(&self, time: f32) -> Arena {
+        self.arena.at(&self.header, time)
+    }
+
+    /// See `Arena::tile_fall_time`
+    pub fn tile_fall_time(&self, x: u16, y: u16) -> Option<f32> {
+        self.arena.tile_fall_time(&self.header, x, y)
+    }
+
+    /// Fall time of every tile, in the same order as `Arena::data`
+    pub fn tile_fall_times(&self) -> Vec<Option<f32>> {
+        (0..ARENA_SIZE)
+            .flat_map(|y| (0..ARENA_SIZE).map(move |x| (x, y)))
+            .map(|(x, y)| self.tile_fall_time(x, y))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tiles_fall_as_the_arena_shrinks() {
+        let mut spawnset = Spawnset::<V3Enemies> {
+            header: Header { shrink_start_radius: 50., shrink_end_radius: 20., shrink_rate: 0.5, ..Default::default() },
+            arena: Arena { data: [0.; ARENA_TILES] },
+            spawns_header: SpawnsHeader::default(),
+            spawns: vec![],
+            settings: None,
+        };
+        *spawnset.arena.get_tile_mut(0, 0) = VOID_HEIGHT;
+
+        assert_eq!(spawnset.header.shrink_radius_at(10.), 45.);
+        assert_eq!(spawnset.header.shrink_radius_at(1000.), 20.);
+
+        // 10 tiles east of the center is 40 units away, reached after 20 seconds
+        assert_eq!(spawnset.tile_fall_time(35, 25), Some(20.));
+        assert_eq!(spawnset.tile_fall_time(25, 25), None);
+        assert_eq!(spawnset.tile_fall_time(50, 50), Some(0.));
+        assert_eq!(spawnset.tile_fall_time(0, 0), None);
+
+        let arena = spawnset.arena_at(19.9);
+        assert!(arena.is_solid(35, 25) && arena.is_solid(25, 25) && !arena.is_solid(50, 50));
+        let arena = spawnset.arena_at(20.);
+        assert!(!arena.is_solid(35, 25));
+        assert_eq!(*arena.get_tile(35, 25), VOID_HEIGHT);
+
+        let times = spawnset.tile_fall_times();
+        assert_eq!(times.len(), ARENA_TILES);
+        assert_eq!(times[25 * 51 + 35], Some(20.));
+        // Everything within the end radius stays up
+        let standing = (0..ARENA_SIZE).flat_map(|y| (0..ARENA_SIZE).map(move |x| (x, y)))
+            .filter(|&(x, y)| spawnset.arena_at(f32::MAX).is_solid(x, y))
+            .all(|(x, y)| Arena::tile_distance(x, y) < 20.);
+        assert!(standing);
+    }
+}
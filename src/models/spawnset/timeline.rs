@@ -0,0 +1,133 @@
+//
+// absolute spawn times and the end loop
+//
+
+use super::*;
+
+const EMPTY_ID: i32 = -1;
+
+/// How much faster every iteration of the end loop is than the previous one,
+/// iteration `n` runs its delays at `1 + n * END_LOOP_SPEEDUP` speed
+pub const END_LOOP_SPEEDUP: f32 = 1. / 8.;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimedSpawn<SpawnType> {
+    pub index: usize, // Into `Spawnset::spawns`
+    pub enemy_type: SpawnType,
+    pub time: f32,
+    pub loop_iteration: Option<usize>, // `None` before the end loop
+}
+
+/// Spawns with their absolute times, in seconds since the run started.
+///
+/// The end loop is everything from the last `Empty` spawn onwards, the whole
+/// list loops if there's no `Empty` and nothing loops if there are no enemies
+/// after the last one. The lengths are the sum of the delays before and after
+/// `loop_start`, like devildaggers.info's `non_loop_length` and `loop_length`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpawnTimeline<SpawnType> {
+    pub spawns: Vec<TimedSpawn<SpawnType>>,
+    pub loop_start: Option<usize>,
+    pub non_loop_length: f32,
+    pub loop_length: Option<f32>,
+}
+
+impl<SpawnType: Copy + ToPrimitive> Spawnset<SpawnType> {
+    fn is_empty_spawn(spawn: &Spawn<SpawnType>) -> bool {
+        spawn.enemy_type.to_i32() == Some(EMPTY_ID)
+    }
+
+    /// Index of the first spawn of the end loop, `None` if the spawnset doesn't loop
+    pub fn loop_start(&self) -> Option<usize> {
+        let start = self.spawns.iter().rposition(Self::is_empty_spawn).unwrap_or(0);
+        self.spawns[start..].iter().any(|spawn| !Self::is_empty_spawn(spawn)).then_some(start)
+    }
+
+    /// Enemy spawns in order up to `loop_iterations` passes of the end loop, `Empty` spawns are left out
+    pub fn timeline(&self, loop_iterations: usize) -> SpawnTimeline<SpawnType> {
+        let loop_start = self.loop_start();
+        let split = loop_start.unwrap_or(self.spawns.len());
+        let mut spawns = vec![];
+
+        let mut time = 0.;
+        for (index, spawn) in self.spawns[..split].iter().enumerate() {
+            time += spawn.delay;
+            if !Self::is_empty_spawn(spawn) {
+                spawns.push(TimedSpawn { index, enemy_type: spawn.enemy_type, time, loop_iteration: None });
+            }
+        }
+        let non_loop_length = time;
+
+        let loop_length = loop_start.map(|_| self.spawns[split..].iter().map(|spawn| spawn.delay).sum());
+        if loop_start.is_some() {
+            for iteration in 0..loop_iterations {
+                let speed = 1. + iteration as f32 * END_LOOP_SPEEDUP;
+                for (offset, spawn) in self.spawns[split..].iter().enumerate() {
+                    time += spawn.delay / speed;
+                    if !Self::is_empty_spawn(spawn) {
+                        spawns.push(TimedSpawn { index: split + offset, enemy_type: spawn.enemy_type, time, loop_iteration: Some(iteration) });
+                    }
+                }
+            }
+        }
+
+        SpawnTimeline { spawns, loop_start, non_loop_length, loop_length }
+    }
+}
+
+impl<SpawnType> SpawnTimeline<SpawnType> {
+    /// Spawns that happen up to and including `time`
+    pub fn until(&self, time: f32) -> &[TimedSpawn<SpawnType>] {
+        &self.spawns[..self.spawns.partition_point(|spawn| spawn.time <= time)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spawnset(spawns: &[(V3Enemies, f32)]) -> Spawnset<V3Enemies> {
+        Spawnset {
+            header: Header::default(),
+            arena: Arena::default(),
+            spawns_header: SpawnsHeader::default(),
+            spawns: spawns.iter().map(|&(enemy_type, delay)| Spawn { enemy_type, delay, ..Default::default() }).collect(),
+            settings: None,
+        }
+    }
+
+    #[test]
+    fn absolute_times_and_loop() {
+        let spawnset = spawnset(&[
+            (V3Enemies::Squid1, 3.),
+            (V3Enemies::Empty, 2.),
+            (V3Enemies::Squid2, 5.),
+            (V3Enemies::Empty, 4.),
+            (V3Enemies::Gigapede, 4.),
+        ]);
+        let timeline = spawnset.timeline(2);
+        assert_eq!(timeline.loop_start, Some(3));
+        assert_eq!(timeline.non_loop_length, 10.);
+        assert_eq!(timeline.loop_length, Some(8.));
+
+        let times = timeline.spawns.iter().map(|spawn| (spawn.index, spawn.time, spawn.loop_iteration)).collect::<Vec<_>>();
+        assert_eq!(times, vec![(0, 3., None), (2, 10., None), (4, 18., Some(0)), (4, 18. + 8. / 1.125, Some(1))]);
+        assert_eq!(timeline.until(10.).len(), 2);
+        assert_eq!(spawnset.timeline(0).spawns.len(), 2);
+    }
+
+    #[test]
+    fn loop_edge_cases() {
+        // No separator, the whole list loops
+        let timeline = spawnset(&[(V3Enemies::Squid1, 1.), (V3Enemies::Spider1, 2.)]).timeline(1);
+        assert_eq!((timeline.loop_start, timeline.non_loop_length, timeline.loop_length), (Some(0), 0., Some(3.)));
+        assert_eq!(timeline.spawns.len(), 2);
+
+        // Nothing after the last separator, nothing loops
+        let timeline = spawnset(&[(V3Enemies::Squid1, 1.), (V3Enemies::Empty, 2.)]).timeline(5);
+        assert_eq!((timeline.loop_start, timeline.non_loop_length, timeline.loop_length), (None, 3., None));
+        assert_eq!(timeline.spawns.len(), 1);
+
+        assert_eq!(spawnset(&[]).timeline(3).spawns, vec![]);
+    }
+}
@@ -0,0 +1,160 @@
+//
+// converting spawnsets to newer game versions
+//
+
+use super::*;
+
+// V2 and V3 spawnsets share world version 9, V1 was the only one on 8
+const V1_WORLD_VERSION: i32 = 8;
+const WORLD_VERSION: i32 = 9;
+
+/// The game version a spawnset's enemy ids belong to, V2 can't be told apart
+/// from V3 so it doesn't have one, see `AnySpawnset`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpawnsetVersion {
+    V1,
+    V3,
+}
+
+fn map_enemies<From, To>(spawnset: Spawnset<From>, map: impl Fn(From) -> To) -> Spawnset<To> {
+    Spawnset {
+        header: spawnset.header,
+        arena: spawnset.arena,
+        spawns_header: spawnset.spawns_header,
+        spawns: spawnset.spawns.into_iter()
+            .map(|spawn| Spawn {
+                enemy_type: map(spawn.enemy_type),
+                delay: spawn.delay,
+                _u1: spawn._u1,
+                _u2: spawn._u2,
+                _u3: spawn._u3,
+                _u4: spawn._u4,
+                _u5: spawn._u5,
+            })
+            .collect(),
+        settings: spawnset.settings,
+    }
+}
+
+/// V2 kept every V1 enemy id, only the world version changes
+impl From<Spawnset<V1Enemies>> for Spawnset<V2Enemies> {
+    fn from(spawnset: Spawnset<V1Enemies>) -> Self {
+        let mut upgraded = map_enemies(spawnset, |enemy| match enemy {
+            V1Enemies::Squid1 => V2Enemies::Squid1,
+            V1Enemies::Squid2 => V2Enemies::Squid2,
+            V1Enemies::Centipede => V2Enemies::Centipede,
+            V1Enemies::Spider1 => V2Enemies::Spider1,
+            V1Enemies::Leviathan => V2Enemies::Leviathan,
+            V1Enemies::Gigapede => V2Enemies::Gigapede,
+            V1Enemies::Empty => V2Enemies::Empty,
+        });
+        upgraded.header.world_version = WORLD_VERSION;
+        upgraded
+    }
+}
+
+/// Andras was never finished and doesn't spawn anything in V2, so it becomes
+/// an empty spawn that keeps its delay. Its id is a Thorn in V3, keeping it
+/// would change the spawnset.
+impl From<Spawnset<V2Enemies>> for Spawnset<V3Enemies> {
+    fn from(spawnset: Spawnset<V2Enemies>) -> Self {
+        map_enemies(spawnset, |enemy| match enemy {
+            V2Enemies::Squid1 => V3Enemies::Squid1,
+            V2Enemies::Squid2 => V3Enemies::Squid2,
+            V2Enemies::Centipede => V3Enemies::Centipede,
+            V2Enemies::Spider1 => V3Enemies::Spider1,
+            V2Enemies::Leviathan => V3Enemies::Leviathan,
+            V2Enemies::Gigapede => V3Enemies::Gigapede,
+            V2Enemies::Squid3 => V3Enemies::Squid3,
+            V2Enemies::Andras => V3Enemies::Empty,
+            V2Enemies::Spider2 => V3Enemies::Spider2,
+            V2Enemies::Empty => V3Enemies::Empty,
+        })
+    }
+}
+
+impl From<Spawnset<V1Enemies>> for Spawnset<V3Enemies> {
+    fn from(spawnset: Spawnset<V1Enemies>) -> Self {
+        Spawnset::<V2Enemies>::from(spawnset).into()
+    }
+}
+
+impl AnySpawnset {
+    /// Picks the enemy version from the header. V2 spawnsets have spawn
+    /// version 4 and world version 9 like the first V3 ones, only the spawn
+    /// versions 5 and 6 that added the settings block are V3 for sure.
+    pub(super) fn detect(header_bytes: &[u8]) -> Option<SpawnsetVersion> {
+        let world_version = header_bytes.get(4..8)?;
+        match i32::from_le_bytes([world_version[0], world_version[1], world_version[2], world_version[3]]) {
+            V1_WORLD_VERSION => Some(SpawnsetVersion::V1),
+            _ => Some(SpawnsetVersion::V3),
+        }
+    }
+
+    pub fn version(&self) -> SpawnsetVersion {
+        match self {
+            AnySpawnset::V1(_) => SpawnsetVersion::V1,
+            AnySpawnset::V3(_) => SpawnsetVersion::V3,
+        }
+    }
+
+    /// The spawnset as it would be loaded by the current game
+    pub fn into_v3(self) -> Spawnset<V3Enemies> {
+        match self {
+            AnySpawnset::V1(spawnset) => spawnset.into(),
+            AnySpawnset::V3(spawnset) => spawnset,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spawnset<T: Default>(world_version: i32, enemies: Vec<T>) -> Spawnset<T> {
+        let spawns = enemies.into_iter().map(|enemy_type| Spawn { enemy_type, delay: 1.5, ..Default::default() }).collect::<Vec<_>>();
+        Spawnset {
+            header: Header { spawn_version: 4, world_version, ..Default::default() },
+            arena: Arena::default(),
+            spawns_header: SpawnsHeader { spawn_count: spawns.len() as i32, ..Default::default() },
+            spawns,
+            settings: None,
+        }
+    }
+
+    #[test]
+    fn opens_any_version() {
+        let v1 = spawnset(V1_WORLD_VERSION, vec![V1Enemies::Gigapede, V1Enemies::Empty]);
+        let mut buf = vec![];
+        v1.serialize(&mut buf).unwrap();
+        assert_eq!(AnySpawnset::detect(&buf), Some(SpawnsetVersion::V1));
+        assert!(matches!(AnySpawnset::deserialize(&mut &buf[..]).unwrap(), AnySpawnset::V1(_)));
+
+        let v3 = spawnset(WORLD_VERSION, vec![V3Enemies::Ghostpede]);
+        buf.clear();
+        v3.serialize(&mut buf).unwrap();
+        assert_eq!(AnySpawnset::deserialize(&mut &buf[..]).unwrap().version(), SpawnsetVersion::V3);
+        assert_eq!(AnySpawnset::detect(&buf[..7]), None);
+
+        // A V2 spawnset is read as V3, Andras shares its id with the Thorn
+        let v2 = spawnset(WORLD_VERSION, vec![V2Enemies::Andras]);
+        buf.clear();
+        v2.serialize(&mut buf).unwrap();
+        let AnySpawnset::V3(read) = AnySpawnset::deserialize(&mut &buf[..]).unwrap() else { panic!() };
+        assert_eq!(read.spawns[0].enemy_type, V3Enemies::Thorn);
+    }
+
+    #[test]
+    fn upgrades_to_v3() {
+        let v1 = spawnset(V1_WORLD_VERSION, vec![V1Enemies::Squid1, V1Enemies::Leviathan, V1Enemies::Empty]);
+        let v3 = AnySpawnset::V1(v1).into_v3();
+        assert_eq!(v3.header.world_version, WORLD_VERSION);
+        assert_eq!(v3.spawns.iter().map(|s| s.enemy_type).collect::<Vec<_>>(), vec![V3Enemies::Squid1, V3Enemies::Leviathan, V3Enemies::Empty]);
+        assert_eq!(v3.spawns_header.spawn_count, 3);
+
+        let v2 = spawnset(WORLD_VERSION, vec![V2Enemies::Andras, V2Enemies::Squid3, V2Enemies::Spider2]);
+        let v3 = Spawnset::<V3Enemies>::from(v2);
+        assert_eq!(v3.spawns.iter().map(|s| s.enemy_type).collect::<Vec<_>>(), vec![V3Enemies::Empty, V3Enemies::Squid3, V3Enemies::Spider2]);
+        assert!(v3.spawns.iter().all(|s| s.delay == 1.5));
+    }
+}